@@ -0,0 +1,207 @@
+// Adapters for hosted job boards (Greenhouse, Lever).
+//
+// Both boards expose a public JSON API keyed by a board token (e.g., "brooklynmuseum"), the
+// functions here turn the API responses into a list of `Posting`s.
+
+use serde::Deserialize;
+
+const GREENHOUSE_API: &str = "https://boards-api.greenhouse.io/v1/boards";
+const LEVER_API: &str = "https://api.lever.co/v0/postings";
+
+// A single job posting as returned by a job board.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Posting {
+    // Board specific id of the posting, stable across runs so it's used as the cache key.
+    pub id: String,
+    pub title: String,
+    pub department: String,
+    pub location: String,
+    // Link to the human readable posting.
+    pub url: String,
+    // Below fields are only known for postings that come from schema.org JobPosting markup, see
+    // the jsonld module.
+    pub date_posted: String,
+    pub valid_through: String,
+    pub salary: String,
+}
+
+impl Posting {
+    // Returns a one line human readable description of the posting.
+    pub fn summary(&self) -> String {
+        let details: Vec<&str> = [
+            self.department.as_str(),
+            self.location.as_str(),
+            self.salary.as_str(),
+        ]
+        .into_iter()
+        .filter(|x| !x.is_empty())
+        .collect();
+        let mut summary = if details.is_empty() {
+            format!("{} {}", self.title, self.url)
+        } else {
+            format!("{} ({}) {}", self.title, details.join(", "), self.url)
+        };
+        if !self.valid_through.is_empty() {
+            summary.push_str(&format!(" apply by {}", self.valid_through));
+        }
+        summary.trim_end().to_string()
+    }
+}
+
+// Returns the api uri listing all the postings of a greenhouse board.
+pub fn greenhouse_api_uri(board: &str) -> String {
+    format!("{}/{}/jobs?content=true", GREENHOUSE_API, board)
+}
+
+// Returns the api uri listing all the postings of a lever board.
+pub fn lever_api_uri(board: &str) -> String {
+    format!("{}/{}?mode=json", LEVER_API, board)
+}
+
+#[derive(Deserialize)]
+struct GreenhouseName {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct GreenhouseJob {
+    id: u64,
+    title: String,
+    #[serde(default)]
+    absolute_url: String,
+    location: Option<GreenhouseName>,
+    #[serde(default)]
+    departments: Vec<GreenhouseName>,
+}
+
+#[derive(Deserialize)]
+struct GreenhouseJobs {
+    jobs: Vec<GreenhouseJob>,
+}
+
+// Parses the response of `greenhouse_api_uri`.
+pub fn parse_greenhouse(body: &str) -> Result<Vec<Posting>, serde_json::Error> {
+    let jobs: GreenhouseJobs = serde_json::from_str(body)?;
+    Ok(jobs
+        .jobs
+        .into_iter()
+        .map(|j| Posting {
+            id: j.id.to_string(),
+            title: j.title,
+            department: j
+                .departments
+                .into_iter()
+                .map(|d| d.name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            location: j.location.map(|l| l.name).unwrap_or_default(),
+            url: j.absolute_url,
+            ..Default::default()
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Default)]
+struct LeverCategories {
+    #[serde(default)]
+    department: String,
+    #[serde(default)]
+    location: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LeverPosting {
+    id: String,
+    text: String,
+    #[serde(default)]
+    hosted_url: String,
+    #[serde(default)]
+    categories: LeverCategories,
+}
+
+// Parses the response of `lever_api_uri`.
+pub fn parse_lever(body: &str) -> Result<Vec<Posting>, serde_json::Error> {
+    let postings: Vec<LeverPosting> = serde_json::from_str(body)?;
+    Ok(postings
+        .into_iter()
+        .map(|p| Posting {
+            id: p.id,
+            title: p.text,
+            department: p.categories.department,
+            location: p.categories.location,
+            url: p.hosted_url,
+            ..Default::default()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_greenhouse() -> Result<(), Box<dyn std::error::Error>> {
+        let body = r#"{
+            "jobs": [
+                {
+                    "id": 127817,
+                    "title": "Assistant Curator",
+                    "absolute_url": "https://boards.greenhouse.io/museum/jobs/127817",
+                    "location": {"name": "Brooklyn, NY"},
+                    "departments": [{"id": 1, "name": "Curatorial"}],
+                    "updated_at": "2022-10-01T12:00:00-04:00"
+                },
+                {"id": 5, "title": "Guard", "absolute_url": "", "location": null}
+            ],
+            "meta": {"total": 2}
+        }"#;
+        let postings = parse_greenhouse(body)?;
+        assert_eq!(
+            postings,
+            vec![
+                Posting {
+                    id: "127817".into(),
+                    title: "Assistant Curator".into(),
+                    department: "Curatorial".into(),
+                    location: "Brooklyn, NY".into(),
+                    url: "https://boards.greenhouse.io/museum/jobs/127817".into(),
+                    ..Default::default()
+                },
+                Posting {
+                    id: "5".into(),
+                    title: "Guard".into(),
+                    ..Default::default()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_lever() -> Result<(), Box<dyn std::error::Error>> {
+        let body = r#"[
+            {
+                "id": "5ac21346-8e0c-4494-8e7a-3eb92ff77902",
+                "text": "Curator of Photography",
+                "hostedUrl": "https://jobs.lever.co/icp/5ac21346",
+                "categories": {"department": "Exhibitions", "location": "New York", "team": "Curatorial"}
+            }
+        ]"#;
+        let postings = parse_lever(body)?;
+        assert_eq!(postings.len(), 1);
+        assert_eq!(postings[0].id, "5ac21346-8e0c-4494-8e7a-3eb92ff77902");
+        assert_eq!(
+            postings[0].summary(),
+            "Curator of Photography (Exhibitions, New York) https://jobs.lever.co/icp/5ac21346"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_greenhouse("<html></html>").is_err());
+        assert!(parse_lever(r#"{"jobs": []}"#).is_err());
+    }
+}
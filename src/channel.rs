@@ -0,0 +1,183 @@
+// Notification channels, they route the notifications of targets to the people that care about
+// them.
+//
+// A channel holds the addresses of each reporting backend, e.g.,
+//   contemporary:
+//     telegram: -100123456
+//     email: [curator@museum.org, registrar@museum.org]
+//     webhook: https://hooks.museum.org/lmk
+// Targets name the channels their notifications go to, targets that don't go to the "default"
+// channel. Backends a target's channels have no address for fall back to the default channel.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Deserializer};
+
+#[derive(Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(transparent)]
+pub struct Channel {
+    // Backend name (see Sender::name) -> addresses.
+    #[serde(deserialize_with = "deserialize_addrs")]
+    pub addrs: BTreeMap<String, Vec<String>>,
+}
+
+// Addresses are given as a single value or a list of values, e.g., a telegram chat id or a list of
+// email addresses.
+fn deserialize_addrs<'de, D>(deserializer: D) -> Result<BTreeMap<String, Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    let scalar = |x: &serde_yaml::Value| -> Result<String, D::Error> {
+        match x {
+            serde_yaml::Value::String(x) => Ok(x.clone()),
+            serde_yaml::Value::Number(x) => Ok(x.to_string()),
+            x => Err(D::Error::custom(format!("invalid address {:?}", x))),
+        }
+    };
+    BTreeMap::<String, serde_yaml::Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(backend, value)| {
+            let addrs = match &value {
+                serde_yaml::Value::Sequence(xs) => xs.iter().map(scalar).collect(),
+                x => scalar(x).map(|x| vec![x]),
+            }?;
+            Ok((backend, addrs))
+        })
+        .collect()
+}
+
+// The channels by name.
+#[derive(Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(transparent)]
+pub struct Channels {
+    pub channels: HashMap<String, Channel>,
+}
+
+impl Channels {
+    pub const DEFAULT: &str = "default";
+
+    // Sets the address of backend in the default channel, unless the default channel has its own
+    // addresses for backend. Used for the addresses given by flags, e.g., --telegram-chat-id.
+    pub fn set_default_addr(&mut self, backend: &str, addr: String) {
+        self.channels
+            .entry(Self::DEFAULT.to_string())
+            .or_default()
+            .addrs
+            .entry(backend.to_string())
+            .or_insert_with(|| vec![addr]);
+    }
+
+    // Returns the addresses of backend in the channels, without duplicates. If the channels have
+    // none (or no channels are given), the addresses of the default channel are returned.
+    pub fn addrs(&self, channels: &[String], backend: &str) -> Vec<String> {
+        let mut addrs: Vec<String> = vec![];
+        for x in channels
+            .iter()
+            .filter_map(|x| self.channels.get(x))
+            .filter_map(|x| x.addrs.get(backend))
+            .flatten()
+        {
+            if !addrs.contains(x) {
+                addrs.push(x.clone());
+            }
+        }
+        if addrs.is_empty() {
+            if let Some(x) = self.channels.get(Self::DEFAULT) {
+                addrs = x.addrs.get(backend).cloned().unwrap_or_default();
+            }
+        }
+        addrs
+    }
+
+    // Checks the addresses of the backends that have a fixed format: telegram chat ids are
+    // numbers and email addresses have to parse.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, channel) in &self.channels {
+            for (backend, addrs) in &channel.addrs {
+                for addr in addrs {
+                    let valid = match backend.as_str() {
+                        "telegram" => addr.parse::<i64>().is_ok(),
+                        "email" => addr.parse::<lettre::message::Mailbox>().is_ok(),
+                        _ => true,
+                    };
+                    if !valid {
+                        return Err(format!(
+                            "channel {:?} has invalid {} address {:?}",
+                            name, backend, addr
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.channels.contains_key(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels() -> Result<(), Box<dyn std::error::Error>> {
+        let mut channels: Channels = serde_yaml::from_str(
+            r#"
+contemporary:
+  telegram: -100123
+  email: [curator@museum.org, registrar@museum.org]
+photography:
+  email: curator@museum.org
+  webhook: https://hooks.museum.org/lmk
+"#,
+        )?;
+        channels.set_default_addr("telegram", "-727046961".to_string());
+        let names = |xs: &[&str]| xs.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            channels.addrs(&names(&["contemporary"]), "telegram"),
+            vec!["-100123"]
+        );
+        assert_eq!(
+            channels.addrs(&names(&["contemporary", "photography"]), "email"),
+            vec!["curator@museum.org", "registrar@museum.org"]
+        );
+        assert!(channels
+            .addrs(&names(&["contemporary"]), "webhook")
+            .is_empty());
+        // Channels without an address of the backend fall back to the default channel.
+        assert_eq!(
+            channels.addrs(&names(&["photography"]), "telegram"),
+            vec!["-727046961"]
+        );
+        assert_eq!(channels.addrs(&[], "telegram"), vec!["-727046961"]);
+        assert!(channels.addrs(&[], "email").is_empty());
+        assert!(channels.contains("default"));
+
+        // The default channel of the config takes precedence over the flags.
+        let mut channels: Channels = serde_yaml::from_str("default:\n  telegram: -1\n")?;
+        channels.set_default_addr("telegram", "-727046961".to_string());
+        assert_eq!(channels.addrs(&[], "telegram"), vec!["-1"]);
+
+        assert!(serde_yaml::from_str::<Channels>("default:\n  telegram: {id: 1}\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> Result<(), Box<dyn std::error::Error>> {
+        let channels: Channels = serde_yaml::from_str(
+            "contemporary:\n  telegram: -100123\n  email: Curator <curator@museum.org>\n",
+        )?;
+        assert_eq!(channels.validate(), Ok(()));
+        let channels: Channels = serde_yaml::from_str("contemporary:\n  telegram: '@curators'\n")?;
+        assert_eq!(
+            channels.validate(),
+            Err("channel \"contemporary\" has invalid telegram address \"@curators\"".to_string())
+        );
+        let channels: Channels = serde_yaml::from_str("contemporary:\n  email: curator\n")?;
+        assert!(channels.validate().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+// Link discovery for crawl targets.
+//
+// A crawl target starts at a page (or a sitemap.xml) and follows the links that match a pattern,
+// each discovered page is a match candidate of its own.
+
+use itertools::Itertools;
+use regex::Regex;
+use scraper::{Html, Selector};
+use url::Url;
+
+// Returns the links in the html page at base whose absolute url matches follow.
+pub fn links(base: &str, body: &str, follow: &Regex) -> Vec<String> {
+    let base = match Url::parse(base) {
+        Ok(base) => base,
+        Err(e) => {
+            log::warn!("can't resolve links of {}: {}", base, e);
+            return vec![];
+        }
+    };
+    let selector = Selector::parse("a[href]").unwrap();
+    let page = Html::parse_document(body);
+    page.select(&selector)
+        .filter_map(|x| x.value().attr("href"))
+        .filter_map(|href| base.join(href).ok())
+        .map(|mut url| {
+            // Links to sections of the same page are the same page.
+            url.set_fragment(None);
+            url.to_string()
+        })
+        .filter(|url| follow.is_match(url))
+        .unique()
+        .collect()
+}
+
+// The urls listed in a sitemap.
+#[derive(PartialEq, Debug, Default)]
+pub struct Sitemap {
+    // Pages listed in a <urlset>.
+    pub pages: Vec<String>,
+    // Nested sitemaps listed in a <sitemapindex>.
+    pub sitemaps: Vec<String>,
+}
+
+// Parses body as a sitemap, returns None if body isn't a sitemap.
+pub fn parse_sitemap(body: &str) -> Option<Sitemap> {
+    let page = Html::parse_document(body);
+    let locs = |selector: &str| -> Vec<String> {
+        let selector = Selector::parse(selector).unwrap();
+        page.select(&selector)
+            .map(|x| x.text().collect::<String>().trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect()
+    };
+    let sitemap = Sitemap {
+        pages: locs("urlset url loc"),
+        sitemaps: locs("sitemapindex sitemap loc"),
+    };
+    let root = Selector::parse("urlset, sitemapindex").unwrap();
+    page.select(&root).next().map(|_| sitemap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_links() {
+        let body = r#"
+            <a href="/jobs/1">Curator</a>
+            <a href="/jobs/1#apply">Apply</a>
+            <a href="2">Registrar</a>
+            <a href="https://other.org/jobs/3">Elsewhere</a>
+            <a href="/about">About</a>
+            <a>No link</a>
+        "#;
+        let follow = Regex::new(r"museum\.org/jobs/\d+").unwrap();
+        assert_eq!(
+            links("https://museum.org/jobs/", body, &follow),
+            vec!["https://museum.org/jobs/1", "https://museum.org/jobs/2"]
+        );
+        assert!(links("not a url", body, &follow).is_empty());
+    }
+
+    #[test]
+    fn test_parse_sitemap() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://museum.org/jobs/1</loc><lastmod>2022-10-01</lastmod></url>
+              <url><loc>
+                https://museum.org/jobs/2
+              </loc></url>
+            </urlset>"#;
+        assert_eq!(
+            parse_sitemap(body),
+            Some(Sitemap {
+                pages: vec![
+                    "https://museum.org/jobs/1".into(),
+                    "https://museum.org/jobs/2".into()
+                ],
+                ..Default::default()
+            })
+        );
+
+        let body = r#"<sitemapindex>
+              <sitemap><loc>https://museum.org/sitemap-jobs.xml</loc></sitemap>
+            </sitemapindex>"#;
+        assert_eq!(
+            parse_sitemap(body),
+            Some(Sitemap {
+                sitemaps: vec!["https://museum.org/sitemap-jobs.xml".into()],
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(parse_sitemap("<html><body>jobs</body></html>"), None);
+    }
+}
@@ -1,6 +1,12 @@
 // Simple KV store.
 
-use rusqlite::{Connection, Result};
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::time::UNIX_EPOCH;
+
+use crate::error::LmkError;
+
+type Result<T, E = LmkError> = std::result::Result<T, E>;
 
 pub struct Db {
     connection: Connection,
@@ -9,20 +15,35 @@ pub struct Db {
 impl Db {
     // Creates w/ the given sqllite3 file.
     pub fn new(db_path: &str) -> Result<Self> {
-        let connection = Connection::open(db_path)?;
-        connection.execute(
-            "create table if not exists kv (key text unique, value text)",
-            (),
-        )?;
-        Ok(Db { connection })
+        Self::init(Connection::open(db_path)?)
     }
     #[cfg(test)]
     pub fn new_in_memory() -> Result<Self> {
-        let connection = Connection::open_in_memory()?;
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    // Creates the tables if they don't exist yet.
+    fn init(connection: Connection) -> Result<Self> {
         connection.execute(
             "create table if not exists kv (key text unique, value text)",
             (),
         )?;
+        // Urls discovered by crawl targets, first_seen is seconds since unix epoch.
+        connection.execute(
+            "create table if not exists seen_urls (target text, url text, first_seen integer, unique(target, url))",
+            (),
+        )?;
+        // Values observed over time, e.g., by threshold targets. ts is seconds since unix epoch.
+        connection.execute(
+            "create table if not exists history (key text, ts integer, value real)",
+            (),
+        )?;
+        // Matches reported for a target, warc_record_ids are the space separated ids of the WARC
+        // records of the pages the match was found in (empty if archiving was off).
+        connection.execute(
+            "create table if not exists match_history (target text, match text, first_seen integer, warc_record_ids text)",
+            (),
+        )?;
         Ok(Db { connection })
     }
     pub fn get(&self, key: &str) -> Option<String> {
@@ -46,6 +67,121 @@ impl Db {
             .execute("REPLACE INTO kv (key, value) VALUES (?1, ?2)", (key, value))?;
         Ok(())
     }
+
+    // Returns the urls recorded for target with add_seen_url.
+    pub fn seen_urls(&self, target: &str) -> HashSet<String> {
+        let mut stmt = match self
+            .connection
+            .prepare("SELECT url FROM seen_urls WHERE target = ?")
+        {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("failed to read from db... {}", e);
+                return HashSet::new();
+            }
+        };
+        let urls = match stmt.query_map(rusqlite::params![target], |row| row.get(0)) {
+            Ok(rows) => rows.filter_map(|x| x.ok()).collect(),
+            Err(e) => {
+                eprintln!("failed to read from db... {}", e);
+                HashSet::new()
+            }
+        };
+        urls
+    }
+
+    // Appends value to the history of key.
+    pub fn add_history(&mut self, key: &str, value: f64) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO history (key, ts, value) VALUES (?1, ?2, ?3)",
+            (key, now_secs(), value),
+        )?;
+        Ok(())
+    }
+
+    // Returns the latest value in the history of key.
+    pub fn last_history(&self, key: &str) -> Option<f64> {
+        self.connection
+            .query_row(
+                "SELECT value FROM history WHERE key = ? ORDER BY ts DESC, rowid DESC LIMIT 1",
+                rusqlite::params![key],
+                |row| row.get(0),
+            )
+            .ok()
+    }
+
+    // Records that url was seen for target, urls that were already seen keep their first_seen.
+    pub fn add_seen_url(&mut self, target: &str, url: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR IGNORE INTO seen_urls (target, url, first_seen) VALUES (?1, ?2, ?3)",
+            (target, url, now_secs()),
+        )?;
+        Ok(())
+    }
+
+    // Records that match was reported for target. A match is only recorded the first time, e.g.,
+    // retries of undelivered matches keep their first record.
+    pub fn add_match(&mut self, target: &str, m: &str, warc_record_ids: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO match_history (target, match, first_seen, warc_record_ids) SELECT ?1, ?2, ?3, ?4 \
+             WHERE NOT EXISTS (SELECT 1 FROM match_history WHERE target = ?1 AND match = ?2)",
+            (target, m, now_secs(), warc_record_ids),
+        )?;
+        Ok(())
+    }
+
+    // Returns when match was first reported for target, in seconds since unix epoch.
+    pub fn first_seen(&self, target: &str, m: &str) -> Option<u64> {
+        self.connection
+            .query_row(
+                "SELECT MIN(first_seen) FROM match_history WHERE target = ?1 AND match = ?2",
+                (target, m),
+                |row| row.get(0),
+            )
+            .ok()
+            .flatten()
+    }
+
+    // Returns the (match, warc_record_ids) recorded for target with add_match, oldest first.
+    #[cfg(test)]
+    pub fn matches(&self, target: &str) -> Vec<(String, String)> {
+        let mut stmt = match self.connection.prepare(
+            "SELECT match, warc_record_ids FROM match_history WHERE target = ? ORDER BY rowid",
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("failed to read from db... {}", e);
+                return vec![];
+            }
+        };
+        let matches = match stmt.query_map(rusqlite::params![target], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        }) {
+            Ok(rows) => rows.filter_map(|x| x.ok()).collect(),
+            Err(e) => {
+                eprintln!("failed to read from db... {}", e);
+                vec![]
+            }
+        };
+        matches
+    }
+
+    // Moves the first_seen of the matches of target secs seconds into the past.
+    #[cfg(test)]
+    pub fn backdate_matches(&mut self, target: &str, secs: u64) -> Result<()> {
+        self.connection.execute(
+            "UPDATE match_history SET first_seen = first_seen - ?2 WHERE target = ?1",
+            (target, secs),
+        )?;
+        Ok(())
+    }
+}
+
+// Returns the seconds since unix epoch.
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
 }
 
 #[cfg(test)]
@@ -71,4 +207,57 @@ mod tests {
         assert_eq!(db.get("a"), Some("q".to_string()));
         Ok(())
     }
+
+    #[test]
+    fn test_seen_urls() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        assert!(db.seen_urls("t1").is_empty());
+        db.add_seen_url("t1", "https://a")?;
+        db.add_seen_url("t1", "https://b")?;
+        db.add_seen_url("t1", "https://a")?;
+        db.add_seen_url("t2", "https://c")?;
+        assert_eq!(
+            db.seen_urls("t1"),
+            HashSet::from(["https://a".to_string(), "https://b".to_string()])
+        );
+        assert_eq!(db.seen_urls("t2"), HashSet::from(["https://c".to_string()]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_history() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        assert_eq!(db.last_history("a"), None);
+        db.add_history("a", 1.5)?;
+        db.add_history("a", 2.0)?;
+        db.add_history("b", 7.0)?;
+        assert_eq!(db.last_history("a"), Some(2.0));
+        assert_eq!(db.last_history("b"), Some(7.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        assert!(db.matches("t1").is_empty());
+        db.add_match("t1", "Curator", "<urn:uuid:1> <urn:uuid:2>")?;
+        db.add_match("t1", "Registrar", "")?;
+        db.add_match("t2", "Guard", "")?;
+        db.add_match("t1", "Curator", "<urn:uuid:3>")?;
+        assert_eq!(
+            db.matches("t1"),
+            vec![
+                (
+                    "Curator".to_string(),
+                    "<urn:uuid:1> <urn:uuid:2>".to_string()
+                ),
+                ("Registrar".to_string(), String::new())
+            ]
+        );
+        assert!(db
+            .first_seen("t1", "Curator")
+            .is_some_and(|x| x <= now_secs()));
+        assert_eq!(db.first_seen("t2", "Curator"), None);
+        Ok(())
+    }
 }
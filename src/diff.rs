@@ -0,0 +1,127 @@
+// Change detection for diff mode targets.
+//
+// The text of a region of the page is normalized into lines so that markup or whitespace only
+// changes don't count, and changes are reported as a line based unified diff.
+
+use scraper::{ElementRef, Html, Node, Selector};
+use similar::{ChangeTag, TextDiff};
+
+// Diffs longer than this are truncated in notifications.
+const MAX_DIFF_LINES: usize = 40;
+
+// Returns the text of the elements of page matching selector, one line per line of text with
+// whitespace collapsed and empty lines dropped.
+pub fn normalized_text(page: &Html, selector: &Selector) -> String {
+    page.select(selector)
+        .flat_map(text_nodes)
+        .flat_map(|x| x.lines())
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Returns the text nodes under element, skipping scripts and styles.
+fn text_nodes(element: ElementRef<'_>) -> impl Iterator<Item = &str> {
+    element.descendants().filter_map(|node| match node.value() {
+        Node::Text(text) => {
+            let in_script = node
+                .parent()
+                .and_then(|p| p.value().as_element())
+                .is_some_and(|e| matches!(e.name(), "script" | "style"));
+            if in_script {
+                None
+            } else {
+                Some(&**text)
+            }
+        }
+        _ => None,
+    })
+}
+
+// The difference between two normalized texts.
+#[derive(PartialEq, Debug)]
+pub struct LineDiff {
+    // Number of removed plus added lines.
+    pub changed_lines: usize,
+    // Unified diff of the change, truncated to MAX_DIFF_LINES.
+    pub unified: String,
+}
+
+pub fn line_diff(old: &str, new: &str) -> LineDiff {
+    // Both texts are terminated with a newline so that the last line doesn't show up as changed
+    // just for gaining a successor.
+    let old = format!("{}\n", old);
+    let new = format!("{}\n", new);
+    let diff = TextDiff::from_lines(&old, &new);
+    let changed_lines = diff
+        .iter_all_changes()
+        .filter(|x| x.tag() != ChangeTag::Equal)
+        .count();
+    let unified = diff
+        .unified_diff()
+        .context_radius(1)
+        .header("before", "after")
+        .to_string();
+    let num_lines = unified.lines().count();
+    let mut lines: Vec<_> = unified.lines().take(MAX_DIFF_LINES).collect();
+    let more = format!(
+        "... ({} more lines)",
+        num_lines.saturating_sub(MAX_DIFF_LINES)
+    );
+    if num_lines > MAX_DIFF_LINES {
+        lines.push(&more);
+    }
+    LineDiff {
+        changed_lines,
+        unified: lines.join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalized_text() {
+        let page = Html::parse_document(
+            r#"
+            <div id="nav">Home</div>
+            <section id="open-positions">
+              <h2>Open   Positions</h2>
+              <script>var x = 1;</script>
+              <ul>
+                <li>Assistant <b>Curator</b></li>
+
+                <li>Registrar</li>
+              </ul>
+            </section>
+            "#,
+        );
+        let selector = Selector::parse("#open-positions").unwrap();
+        assert_eq!(
+            normalized_text(&page, &selector),
+            "Open Positions\nAssistant\nCurator\nRegistrar"
+        );
+    }
+
+    #[test]
+    fn test_line_diff() {
+        let diff = line_diff("a\nb\nc\nd", "a\nb\nC\nd");
+        assert_eq!(diff.changed_lines, 2);
+        assert_eq!(
+            diff.unified,
+            "--- before\n+++ after\n@@ -2,3 +2,3 @@\n b\n-c\n+C\n d"
+        );
+        assert_eq!(line_diff("a\nb", "a\nb").changed_lines, 0);
+    }
+
+    #[test]
+    fn test_line_diff_truncated() {
+        let new: Vec<_> = (0..100).map(|x| x.to_string()).collect();
+        let diff = line_diff("", &new.join("\n"));
+        assert_eq!(diff.changed_lines, 101);
+        assert_eq!(diff.unified.lines().count(), MAX_DIFF_LINES + 1);
+        assert!(diff.unified.ends_with("... (64 more lines)"));
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use crate::error::LmkError;
+use crate::formatting::truncate;
+use crate::myscraper::{Sender, Target};
+use crate::notify::{self, send_with_retries, Pacer};
+
+// Posts embeds to a Discord webhook, addr is the webhook url, e.g.,
+// https://discord.com/api/webhooks/123/XXXX.
+pub struct DiscordSender {
+    client: reqwest::blocking::Client,
+    pacer: Pacer,
+    retry_delay: Duration,
+}
+
+impl Sender for DiscordSender {
+    fn name(&self) -> String {
+        "discord".to_string()
+    }
+
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        self.pacer.wait();
+        let payload = payload(target, &msg, link, Utc::now());
+        let request = self.client.post(addr).json(&payload);
+        send_with_retries(request, "discord", self.retry_delay)
+    }
+}
+
+impl DiscordSender {
+    // Webhooks take 5 messages per 2 seconds.
+    const MIN_INTERVAL: Duration = Duration::from_millis(400);
+    // Max lengths of the title and the description of an embed.
+    const MAX_TITLE_CHARS: usize = 256;
+    const MAX_DESCRIPTION_CHARS: usize = 4096;
+
+    pub fn new() -> Result<Self, LmkError> {
+        Self::with_limits(Self::MIN_INTERVAL, notify::DEFAULT_RETRY_DELAY)
+    }
+
+    fn with_limits(min_interval: Duration, retry_delay: Duration) -> Result<Self, LmkError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(notify::TIMEOUT)
+            .build()
+            .map_err(|e| LmkError::Config(format!("discord: {}", e)))?;
+        Ok(DiscordSender {
+            client,
+            pacer: Pacer::new(min_interval),
+            retry_delay,
+        })
+    }
+}
+
+// Returns the message of the alert msg about target, found at now: an embed titled with the text
+// looked for that links to the match at link.
+fn payload(target: &Target, msg: &str, link: &str, now: DateTime<Utc>) -> Value {
+    let title = if target.text.is_empty() {
+        &target.uri
+    } else {
+        &target.text
+    };
+    json!({
+        "embeds": [{
+            "title": truncate(title, DiscordSender::MAX_TITLE_CHARS),
+            "url": link,
+            "description": truncate(msg, DiscordSender::MAX_DESCRIPTION_CHARS),
+            "timestamp": now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "footer": {"text": target.uri},
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::matchers::{json_decoded, request};
+    use httptest::responders::status_code;
+    use httptest::{all_of, cycle, Expectation};
+
+    fn target() -> Target {
+        Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_payload() {
+        let now = DateTime::parse_from_rfc3339("2022-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            payload(
+                &target(),
+                "Found match: Curator",
+                "https://museum.org/jobs/1",
+                now
+            ),
+            json!({
+                "embeds": [{
+                    "title": "Curator",
+                    "url": "https://museum.org/jobs/1",
+                    "description": "Found match: Curator",
+                    "timestamp": "2022-10-21T07:28:00Z",
+                    "footer": {"text": "https://museum.org/jobs"},
+                }],
+            })
+        );
+        let message = payload(&target(), &"+ Curator\n".repeat(1000), "", now);
+        let description = message["embeds"][0]["description"].as_str().unwrap();
+        assert_eq!(
+            description.chars().count(),
+            DiscordSender::MAX_DESCRIPTION_CHARS
+        );
+    }
+
+    #[test]
+    fn test_discord_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/webhooks/123/XXXX"),
+                request::body(json_decoded(|x: &Value| {
+                    x["embeds"][0]["title"] == json!("Curator")
+                        && x["embeds"][0]["description"] == json!("Found match: Curator")
+                })),
+            ])
+            .times(2)
+            .respond_with(cycle![
+                status_code(429)
+                    .insert_header("Retry-After", "0.01")
+                    .body(r#"{"message": "You are being rate limited.", "retry_after": 0.01}"#),
+                status_code(204),
+            ]),
+        );
+        let sender = DiscordSender::with_limits(Duration::ZERO, Duration::ZERO)?;
+        sender.send(
+            &server.url_str("/api/webhooks/123/XXXX"),
+            &target(),
+            "Found match: Curator".to_string(),
+            &target().uri,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+
+use crate::error::LmkError;
+use crate::formatting::html;
+use crate::myscraper::{Sender, Target};
+
+// How the connection to the SMTP server is secured.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum SmtpSecurity {
+    /// Upgrade the connection with STARTTLS, port 587 by default
+    #[default]
+    Starttls,
+    /// Connect over TLS, port 465 by default
+    Tls,
+    /// No encryption, only meant for local relays, port 25 by default
+    None,
+}
+
+// Sends alert emails over SMTP, addr is the recipient's address. The SMTP credentials are read from
+// the SMTP_USERNAME and SMTP_PASSWORD env variables, e.g.,
+// $ export SMTP_USERNAME=lmk@museum.org SMTP_PASSWORD=<Your password here>
+pub struct EmailSender {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl Sender for EmailSender {
+    fn name(&self) -> String {
+        "email".to_string()
+    }
+
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        let to: Mailbox = addr
+            .parse()
+            .map_err(|e| LmkError::Config(format!("invalid email address {:?}: {}", addr, e)))?;
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(format!("lmk: {}", target.uri))
+            .multipart(MultiPart::alternative_plain_html(
+                format!("{}\n\n{}", msg, link),
+                html(&msg, link),
+            ))
+            .map_err(|e| LmkError::Notify(format!("email: {}", e)))?;
+        self.transport
+            .send(&email)
+            .map_err(|e| LmkError::Notify(format!("email: {}", e)))?;
+        Ok(())
+    }
+}
+
+impl EmailSender {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    // Creates a new Sender that sends from the from address through the SMTP server at host, port
+    // defaults to the port of security.
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        security: SmtpSecurity,
+        from: &str,
+        credentials: Option<Credentials>,
+    ) -> Result<Self, LmkError> {
+        let from = from
+            .parse()
+            .map_err(|e| LmkError::Config(format!("invalid email address {:?}: {}", from, e)))?;
+        let builder = match security {
+            SmtpSecurity::Starttls => SmtpTransport::starttls_relay(host),
+            SmtpSecurity::Tls => SmtpTransport::relay(host),
+            SmtpSecurity::None => Ok(SmtpTransport::builder_dangerous(host)),
+        };
+        let mut builder = builder
+            .map_err(|e| LmkError::Config(format!("smtp: {}", e)))?
+            .timeout(Some(Self::TIMEOUT));
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Some(credentials) = credentials {
+            builder = builder.credentials(credentials);
+        }
+        Ok(EmailSender {
+            transport: builder.build(),
+            from,
+        })
+    }
+
+    // Returns the credentials in the SMTP_USERNAME and SMTP_PASSWORD env variables, None if they
+    // aren't set.
+    pub fn credentials_from_env() -> Result<Option<Credentials>, LmkError> {
+        Self::credentials(
+            std::env::var("SMTP_USERNAME").ok(),
+            std::env::var("SMTP_PASSWORD").ok(),
+        )
+    }
+
+    // Returns the credentials of username and password, which are set together or not at all.
+    fn credentials(
+        username: Option<String>,
+        password: Option<String>,
+    ) -> Result<Option<Credentials>, LmkError> {
+        match (username, password) {
+            (Some(username), Some(password)) => Ok(Some(Credentials::new(username, password))),
+            (None, None) => Ok(None),
+            (Some(_), None) => Err(LmkError::Config(
+                "SMTP_USERNAME is set but SMTP_PASSWORD isn't".to_string(),
+            )),
+            (None, Some(_)) => Err(LmkError::Config(
+                "SMTP_PASSWORD is set but SMTP_USERNAME isn't".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // A local SMTP stand-in that accepts a single connection, returns the port it listens on and
+    // the thread that returns the commands and data it received.
+    fn smtp_stand_in() -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut received = vec![];
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                let command = line.trim_end().to_string();
+                line.clear();
+                let reply: &[u8] = match command.split(' ').next().unwrap() {
+                    "EHLO" => b"250-localhost\r\n250 AUTH PLAIN LOGIN\r\n",
+                    "AUTH" => b"235 2.7.0 Authentication successful\r\n",
+                    "DATA" => {
+                        writer
+                            .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                            .unwrap();
+                        let mut data = String::new();
+                        while !data.ends_with("\r\n.\r\n") {
+                            reader.read_line(&mut data).unwrap();
+                        }
+                        received.push(command);
+                        received.push(data);
+                        writer.write_all(b"250 OK\r\n").unwrap();
+                        continue;
+                    }
+                    "QUIT" => {
+                        received.push(command);
+                        writer.write_all(b"221 Bye\r\n").unwrap();
+                        break;
+                    }
+                    _ => b"250 OK\r\n",
+                };
+                received.push(command);
+                writer.write_all(reply).unwrap();
+            }
+            received
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn test_email_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let (port, handle) = smtp_stand_in();
+        let sender = EmailSender::new(
+            "127.0.0.1",
+            Some(port),
+            SmtpSecurity::None,
+            "lmk <lmk@museum.org>",
+            Some(Credentials::new("lmk".to_string(), "secret".to_string())),
+        )?;
+        let target = Target {
+            uri: "https://museum.org/jobs?a=1&b=2".to_string(),
+            ..Default::default()
+        };
+        sender.send(
+            "curator@museum.org",
+            &target,
+            "Found match: <b>Curator</b>".to_string(),
+            "https://museum.org/jobs/1?a=1&b=2",
+        )?;
+        drop(sender);
+        let received = handle.join().unwrap();
+        assert!(received[0].starts_with("EHLO "));
+        assert!(received.iter().any(|x| x.starts_with("AUTH PLAIN ")));
+        assert!(received.contains(&"MAIL FROM:<lmk@museum.org>".to_string()));
+        assert!(received.contains(&"RCPT TO:<curator@museum.org>".to_string()));
+        let data = &received[received.iter().position(|x| x == "DATA").unwrap() + 1];
+        assert!(data.contains("To: curator@museum.org"));
+        assert!(data.contains("Subject: lmk: https://museum.org/jobs?a=1&b=2"));
+        assert!(data.contains("Content-Type: multipart/alternative"));
+        assert!(data.contains("Content-Type: text/plain"));
+        assert!(data.contains("Found match: <b>Curator</b>"));
+        assert!(data.contains("Content-Type: text/html"));
+        assert!(data.contains("<pre>Found match: &lt;b&gt;Curator&lt;/b&gt;</pre>"));
+        // The HTML part is quoted-printable encoded.
+        assert!(data.contains("href=3D\"https://museum.org/jobs/1?a=3D1&amp;b=3D2\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_email_sender_errors() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(matches!(
+            EmailSender::new(
+                "127.0.0.1",
+                None,
+                SmtpSecurity::None,
+                "not an address",
+                None
+            ),
+            Err(LmkError::Config(_))
+        ));
+        // Nothing listens on the port of a dropped listener.
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let sender = EmailSender::new(
+            "127.0.0.1",
+            Some(port),
+            SmtpSecurity::None,
+            "lmk@museum.org",
+            None,
+        )?;
+        let target = Target::default();
+        assert!(matches!(
+            sender.send("curator", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Config(_))
+        ));
+        assert!(matches!(
+            sender.send("curator@museum.org", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Notify(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_credentials() {
+        assert!(matches!(EmailSender::credentials(None, None), Ok(None)));
+        assert!(matches!(
+            EmailSender::credentials(Some("lmk".to_string()), Some("secret".to_string())),
+            Ok(Some(_))
+        ));
+        assert_eq!(
+            EmailSender::credentials(Some("lmk".to_string()), None).err(),
+            Some(LmkError::Config(
+                "SMTP_USERNAME is set but SMTP_PASSWORD isn't".to_string()
+            ))
+        );
+        assert!(matches!(
+            EmailSender::credentials(None, Some("secret".to_string())),
+            Err(LmkError::Config(_))
+        ));
+    }
+}
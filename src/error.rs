@@ -0,0 +1,77 @@
+// Errors of lmk, by category. Each category exits with its own code, see LmkError::exit_code.
+
+use thiserror::Error;
+
+use crate::fetcher::FetchError;
+
+#[derive(Error, PartialEq, Debug)]
+pub enum LmkError {
+    // The targets file, a flag or the environment is invalid.
+    #[error("config error: {0}")]
+    Config(String),
+    // A page couldn't be fetched, holds the failure status, e.g., "timeout".
+    #[error("fetch error: {0}")]
+    Fetch(String),
+    // A fetched page couldn't be parsed.
+    #[error("parse error: {0}")]
+    Parse(String),
+    // The target cache couldn't be read or written.
+    #[error("storage error: {0}")]
+    Storage(String),
+    // A notification couldn't be sent.
+    #[error("notify error: {0}")]
+    Notify(String),
+    // A notification can never be delivered to its address, e.g., the chat doesn't exist or the
+    // bot was kicked from it, so retrying won't help.
+    #[error("undeliverable: {0}")]
+    Undeliverable(String),
+}
+
+impl LmkError {
+    // Returns the exit code of the process for the error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            LmkError::Config(_) => 2,
+            LmkError::Fetch(_) => 3,
+            LmkError::Parse(_) => 4,
+            LmkError::Storage(_) => 5,
+            LmkError::Notify(_) => 6,
+            LmkError::Undeliverable(_) => 7,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LmkError {
+    fn from(e: rusqlite::Error) -> Self {
+        LmkError::Storage(e.to_string())
+    }
+}
+
+impl From<FetchError> for LmkError {
+    fn from(e: FetchError) -> Self {
+        LmkError::Fetch(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let errors = [
+            LmkError::Config("x".into()),
+            LmkError::Fetch("x".into()),
+            LmkError::Parse("x".into()),
+            LmkError::Storage("x".into()),
+            LmkError::Notify("x".into()),
+            LmkError::Undeliverable("x".into()),
+        ];
+        let codes: Vec<_> = errors.iter().map(|e| e.exit_code()).collect();
+        assert_eq!(codes, vec![2, 3, 4, 5, 6, 7]);
+        assert_eq!(
+            LmkError::Fetch("timeout".into()).to_string(),
+            "fetch error: timeout"
+        );
+    }
+}
@@ -0,0 +1,555 @@
+// Fetching of uris for the scraper.
+//
+// The scraper is generic over the Fetcher, so that tests can serve pages from memory (see
+// FakeFetcher) instead of running a server for every scenario.
+
+use std::io::Read;
+use std::time::Duration;
+
+use opentelemetry::trace::Span;
+use opentelemetry::KeyValue;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use crate::db::now_secs;
+use crate::error::LmkError;
+use crate::session::Cookie;
+
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
+pub enum Method {
+    #[default]
+    Get,
+    Post,
+}
+
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    // Whether the response body is read as bytes into Response.data (e.g., of a PDF) rather than
+    // as text into Response.body.
+    pub binary: bool,
+    // Responses with larger bodies fail with FetchError::TooLarge.
+    pub max_bytes: Option<u64>,
+    // Whether redirects are returned rather than followed, e.g., by logins that set their
+    // cookies on the redirect.
+    pub no_redirects: bool,
+}
+
+impl Request {
+    pub fn get(uri: &str) -> Request {
+        Request {
+            uri: uri.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Request {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+// A successfully fetched uri.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct Response {
+    // The final url of the response, after following redirects.
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    // The body of binary requests, see Request.binary.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<u8>,
+    // Id of the WARC record the response was archived in, see FetchOptions.warc.
+    #[serde(skip)]
+    pub warc_record_id: Option<String>,
+}
+
+#[cfg(test)]
+impl Response {
+    // Returns the value of the first header called name (case insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(x, _)| x.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// Errors of fetching a request, they're recorded as the status in the metrics.
+#[derive(Error, PartialEq, Debug, Clone)]
+pub enum FetchError {
+    // The response is larger than the max_bytes of the request.
+    #[error("too large")]
+    TooLarge,
+    // Holds a short description of the failure, e.g., "timeout".
+    #[error("{0}")]
+    Failed(String),
+}
+
+// Fetcher sends requests for the scraper, following redirects.
+pub trait Fetcher: Sync {
+    fn fetch(&self, request: &Request, span: &mut impl Span) -> Result<Response, FetchError>;
+}
+
+// The default fetcher, sends requests with reqwest.
+pub struct ReqwestFetcher {
+    client: reqwest::blocking::Client,
+    // Sends the requests with no_redirects set.
+    no_redirects_client: reqwest::blocking::Client,
+}
+
+impl ReqwestFetcher {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(timeout: Duration) -> Result<Self, LmkError> {
+        let client = |redirect| {
+            reqwest::blocking::Client::builder()
+                .timeout(timeout)
+                .redirect(redirect)
+                .build()
+                .map_err(|e| LmkError::Config(format!("http client: {}", e)))
+        };
+        Ok(ReqwestFetcher {
+            client: client(reqwest::redirect::Policy::default())?,
+            no_redirects_client: client(reqwest::redirect::Policy::none())?,
+        })
+    }
+}
+
+impl Fetcher for ReqwestFetcher {
+    // Besides http(s), file uris of absolute paths are supported, e.g., file:///tmp/page.html.
+    fn fetch(&self, request: &Request, span: &mut impl Span) -> Result<Response, FetchError> {
+        let uri = &request.uri;
+        if uri.starts_with("file:") {
+            // Paths of the form file://testdata/page.html are rejected, testdata would be the host.
+            let path = url::Url::parse(uri)
+                .ok()
+                .and_then(|x| x.to_file_path().ok())
+                .ok_or_else(|| {
+                    FetchError::Failed(format!(
+                        "invalid file uri {:?}, the path must be absolute",
+                        uri
+                    ))
+                })?;
+            let too_large = std::fs::metadata(&path)
+                .is_ok_and(|x| request.max_bytes.is_some_and(|max| x.len() > max));
+            if too_large {
+                return Err(FetchError::TooLarge);
+            }
+            let read = if request.binary {
+                std::fs::read(&path).map(|data| (String::new(), data))
+            } else {
+                std::fs::read_to_string(&path).map(|body| (body, vec![]))
+            };
+            return match read {
+                Ok((body, data)) => Ok(Response {
+                    url: uri.to_string(),
+                    status: 200,
+                    headers: vec![],
+                    body,
+                    data,
+                    ..Default::default()
+                }),
+                Err(e) => {
+                    span.add_event(
+                        "file-read",
+                        vec![
+                            KeyValue::new("err text", e.to_string()),
+                            KeyValue::new("uri", uri.to_string()),
+                        ],
+                    );
+                    log::warn!("failed to read {:?}, err: {:?}", uri, e);
+                    Err(FetchError::Failed(e.kind().to_string()))
+                }
+            };
+        }
+        let client = if request.no_redirects {
+            &self.no_redirects_client
+        } else {
+            &self.client
+        };
+        let mut builder = match request.method {
+            Method::Get => client.get(uri),
+            Method::Post => client.post(uri),
+        };
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
+        }
+        match builder.send() {
+            Ok(x) => {
+                span.add_event("http-response", http_response_trace_events(&x));
+                let url = x.url().to_string();
+                let status = x.status().as_u16();
+                let headers = x
+                    .headers()
+                    .iter()
+                    .filter_map(|(name, v)| Some((name.to_string(), v.to_str().ok()?.to_string())))
+                    .collect();
+                let max_bytes = request.max_bytes.unwrap_or(u64::MAX);
+                if x.content_length().is_some_and(|x| x > max_bytes) {
+                    return Err(FetchError::TooLarge);
+                }
+                let body = if request.binary {
+                    let mut data = vec![];
+                    // The content length may be missing or wrong.
+                    x.take(max_bytes.saturating_add(1))
+                        .read_to_end(&mut data)
+                        .map(|_| (String::new(), data))
+                        .map_err(|e| FetchError::Failed(e.to_string()))
+                } else {
+                    x.text().map(|body| (body, vec![])).map_err(|e| {
+                        span.add_event(
+                            "failed to convert to text",
+                            vec![
+                                KeyValue::new("err text", e.to_string()),
+                                KeyValue::new("uri", uri.to_string()),
+                            ],
+                        );
+                        FetchError::Failed(error_status(&e))
+                    })
+                };
+                match body {
+                    Ok((body, data)) if (body.len() + data.len()) as u64 > max_bytes => {
+                        Err(FetchError::TooLarge)
+                    }
+                    Ok((body, data)) => Ok(Response {
+                        url,
+                        status,
+                        headers,
+                        body,
+                        data,
+                        ..Default::default()
+                    }),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => {
+                let status = error_status(&e);
+                span.add_event(
+                    "http-response",
+                    vec![
+                        KeyValue::new("err text", e.to_string()),
+                        KeyValue::new("uri", uri.to_string()),
+                        KeyValue::new("status", status.clone()),
+                    ],
+                );
+                log::warn!("failed to scrape {:?}, err: {:?}", uri, e);
+                Err(FetchError::Failed(status))
+            }
+        }
+    }
+}
+
+// Returns the status recorded in the metrics for a failed request.
+fn error_status(e: &reqwest::Error) -> String {
+    if e.is_timeout() {
+        "timeout".to_string()
+    } else if e.is_redirect() {
+        "too many redirects".to_string()
+    } else if e.is_connect() {
+        "connect error".to_string()
+    } else {
+        e.status().map_or("unknown".to_string(), |s| s.to_string())
+    }
+}
+
+// Returns interesting KeyValues from from an http resonse to add to a span event.
+pub fn http_response_trace_events(response: &reqwest::blocking::Response) -> Vec<KeyValue> {
+    let mut result = vec![
+        KeyValue::new(
+            "content_length",
+            response
+                .content_length()
+                .unwrap_or(u64::MAX)
+                .try_into()
+                .unwrap_or(-1),
+        ),
+        KeyValue::new("status_code", response.status().to_string()),
+        KeyValue::new("final_url", response.url().to_string()),
+        KeyValue::new("http_version", format!("{:?}", response.version())),
+        KeyValue::new(
+            "remote_addr",
+            response
+                .remote_addr()
+                .map_or("unknown".to_string(), |addr| addr.to_string()),
+        ),
+    ];
+
+    for (name, v) in response.headers() {
+        if let Ok(v) = v.to_str() {
+            result.push(KeyValue::new(format!("HEADER[{}]", name), v.to_string()));
+        }
+    }
+
+    result
+}
+
+// Fetches pages that are rendered client side through a rendering service, e.g., a headless
+// browser container. The uri is POSTed as json to endpoint, which responds with the rendered
+// html. The request is compatible with browserless' /content api:
+//   {"url": "https://...", "cookies": [{"name": .., "value": .., "domain": .., "path": ..}]}
+pub struct Renderer {
+    pub endpoint: String,
+}
+
+impl Renderer {
+    // Returns the request to the rendering service that renders uri with cookies.
+    pub fn request(&self, uri: &str, cookies: &[Cookie]) -> Request {
+        let now = now_secs();
+        let cookies: Vec<_> = match Url::parse(uri) {
+            Ok(url) => cookies
+                .iter()
+                .filter(|x| !x.is_expired(now) && x.matches(&url))
+                .map(|x| {
+                    serde_json::json!({
+                        "name": x.name, "value": x.value, "domain": x.domain, "path": x.path,
+                    })
+                })
+                .collect(),
+            Err(_) => vec![],
+        };
+        let mut body = serde_json::json!({ "url": uri });
+        if !cookies.is_empty() {
+            body["cookies"] = cookies.into();
+        }
+        Request {
+            method: Method::Post,
+            uri: self.endpoint.clone(),
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+
+    // Returns the rendered page of uri given the response of the rendering service.
+    pub fn response(&self, uri: &str, response: Response) -> Result<Response, LmkError> {
+        if !(200..300).contains(&response.status) {
+            log::warn!(
+                "failed to render {:?}, renderer status: {}",
+                uri,
+                response.status
+            );
+            return Err(LmkError::Fetch(format!("renderer: {}", response.status)));
+        }
+        Ok(Response {
+            url: uri.to_string(),
+            headers: vec![],
+            ..response
+        })
+    }
+}
+
+// A Fetcher that serves responses from memory, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeFetcher {
+    // The result of fetching each uri, uris that aren't in here are 404s.
+    responses: std::collections::HashMap<String, Result<Response, FetchError>>,
+    // The requests that were fetched, in order.
+    pub requests: std::sync::Mutex<Vec<Request>>,
+}
+
+#[cfg(test)]
+impl FakeFetcher {
+    const MAX_REDIRECTS: usize = 10;
+
+    // Serves body at uri.
+    pub fn page(self, uri: &str, body: &str) -> Self {
+        self.respond(
+            uri,
+            Ok(Response {
+                status: 200,
+                body: body.to_string(),
+                ..Default::default()
+            }),
+        )
+    }
+
+    // Redirects uri to location, which may be relative to uri.
+    pub fn redirect(self, uri: &str, location: &str) -> Self {
+        self.respond(
+            uri,
+            Ok(Response {
+                status: 302,
+                headers: vec![("location".to_string(), location.to_string())],
+                ..Default::default()
+            }),
+        )
+    }
+
+    // Fetching uri results in response, e.g., Err(FetchError::Failed("timeout")).
+    pub fn respond(mut self, uri: &str, response: Result<Response, FetchError>) -> Self {
+        self.responses.insert(uri.to_string(), response);
+        self
+    }
+
+    pub fn requested_uris(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|x| x.uri.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Fetcher for FakeFetcher {
+    fn fetch(&self, request: &Request, _span: &mut impl Span) -> Result<Response, FetchError> {
+        self.requests.lock().unwrap().push(request.clone());
+        let mut uri = request.uri.clone();
+        for _ in 0..=Self::MAX_REDIRECTS {
+            let mut response = match self.responses.get(&uri) {
+                Some(response) => response.clone()?,
+                None => Response {
+                    status: 404,
+                    ..Default::default()
+                },
+            };
+            response.url = uri.clone();
+            let size = (response.body.len() + response.data.len()) as u64;
+            if request.max_bytes.is_some_and(|x| size > x) {
+                return Err(FetchError::TooLarge);
+            }
+            match response.header("location") {
+                Some(location)
+                    if (300..400).contains(&response.status) && !request.no_redirects =>
+                {
+                    uri = Url::parse(&uri)
+                        .and_then(|x| x.join(location))
+                        .map_err(|e| FetchError::Failed(e.to_string()))?
+                        .to_string();
+                }
+                _ => return Ok(response),
+            }
+        }
+        Err(FetchError::Failed("too many redirects".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::global;
+    use opentelemetry::trace::Tracer;
+
+    #[test]
+    fn test_fake_fetcher() {
+        let mut span = global::tracer("test").start("test");
+        let fetcher = FakeFetcher::default()
+            .redirect("https://museum.org/jobs", "/careers/")
+            .page("https://museum.org/careers/", "<li>Curator</li>")
+            .redirect("https://museum.org/loop", "https://museum.org/loop")
+            .respond(
+                "https://museum.org/slow",
+                Err(FetchError::Failed("timeout".to_string())),
+            );
+
+        let response = fetcher
+            .fetch(&Request::get("https://museum.org/jobs"), &mut span)
+            .unwrap();
+        assert_eq!(response.url, "https://museum.org/careers/");
+        assert_eq!(response.body, "<li>Curator</li>");
+        assert_eq!(
+            fetcher
+                .fetch(&Request::get("https://museum.org/loop"), &mut span)
+                .unwrap_err(),
+            FetchError::Failed("too many redirects".to_string())
+        );
+        assert_eq!(
+            fetcher
+                .fetch(&Request::get("https://museum.org/slow"), &mut span)
+                .unwrap_err(),
+            FetchError::Failed("timeout".to_string())
+        );
+        assert_eq!(
+            fetcher
+                .fetch(&Request::get("https://museum.org/other"), &mut span)
+                .unwrap()
+                .status,
+            404
+        );
+        assert_eq!(
+            fetcher.requested_uris(),
+            vec![
+                "https://museum.org/jobs",
+                "https://museum.org/loop",
+                "https://museum.org/slow",
+                "https://museum.org/other"
+            ]
+        );
+
+        let request = Request {
+            no_redirects: true,
+            ..Request::get("https://museum.org/jobs")
+        };
+        assert_eq!(fetcher.fetch(&request, &mut span).unwrap().status, 302);
+    }
+
+    #[test]
+    fn test_reqwest_fetcher_timeout() {
+        let server = httptest::Server::run();
+        server.expect(
+            httptest::Expectation::matching(httptest::matchers::request::method_path(
+                "GET", "/slow",
+            ))
+            .respond_with(httptest::responders::delay_and_then(
+                Duration::from_millis(500),
+                httptest::responders::status_code(200),
+            )),
+        );
+        let mut span = global::tracer("test").start("test");
+        let fetcher = ReqwestFetcher::new(Duration::from_millis(50)).unwrap();
+        assert_eq!(
+            fetcher
+                .fetch(&Request::get(&server.url_str("/slow")), &mut span)
+                .unwrap_err(),
+            FetchError::Failed("timeout".to_string())
+        );
+    }
+
+    #[test]
+    fn test_renderer() {
+        let renderer = Renderer {
+            endpoint: "http://localhost:3000/content".to_string(),
+        };
+        let request = renderer.request("https://museum.org/jobs", &[]);
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.uri, "http://localhost:3000/content");
+        assert_eq!(
+            request.body.as_deref(),
+            Some(r#"{"url":"https://museum.org/jobs"}"#)
+        );
+        let rendered = Response {
+            url: "http://localhost:3000/content".to_string(),
+            status: 200,
+            body: "<li>Curator</li>".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            renderer.response("https://museum.org/jobs", rendered),
+            Ok(Response {
+                url: "https://museum.org/jobs".to_string(),
+                status: 200,
+                body: "<li>Curator</li>".to_string(),
+                ..Default::default()
+            })
+        );
+        let failed = Response {
+            status: 500,
+            ..Default::default()
+        };
+        assert_eq!(
+            renderer.response("https://museum.org/jobs", failed),
+            Err(LmkError::Fetch("renderer: 500".to_string()))
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::error::LmkError;
+use crate::myscraper::{Sender, Target};
+
+// Appends messages to a log file, one "<timestamp> [to <addr>] <target uri>: <msg>" entry per
+// message, addr is the names of the target's notification channels.
+pub struct FileSender {
+    path: PathBuf,
+}
+
+impl Sender for FileSender {
+    fn name(&self) -> String {
+        "file".to_string()
+    }
+
+    fn send(&self, addr: &str, target: &Target, msg: String, _link: &str) -> Result<(), LmkError> {
+        let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let write = || -> std::io::Result<()> {
+            let mut f = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            writeln!(f, "{} [to {}] {}: {}", now, addr, target.uri, msg)
+        };
+        write().map_err(|e| LmkError::Notify(format!("{}: {}", self.path.display(), e)))
+    }
+
+    // The log gets every message, channels have no file addresses.
+    fn routed(&self) -> bool {
+        false
+    }
+}
+
+impl FileSender {
+    pub fn new(path: PathBuf) -> Self {
+        FileSender { path }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channels;
+    use crate::myscraper::CompositeSender;
+
+    #[test]
+    fn test_file_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!("lmk-test-alerts-{}.log", std::process::id()));
+        let sender = FileSender::new(path.clone());
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+        sender.send(
+            "default",
+            &target,
+            "Found match: Curator".to_string(),
+            &target.uri,
+        )?;
+        sender.send(
+            "default",
+            &target,
+            "Found match: Registrar".to_string(),
+            &target.uri,
+        )?;
+        let log = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" [to default] https://museum.org/jobs: Found match: Curator"));
+        assert!(lines[1].ends_with(": Found match: Registrar"));
+
+        let sender = FileSender::new(std::env::temp_dir().join("no-such-dir/alerts.log"));
+        assert!(matches!(
+            sender.send("default", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Notify(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_sender_composite() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "lmk-test-composite-alerts-{}.log",
+            std::process::id()
+        ));
+        // No channel has a file address.
+        let channels: Channels = serde_yaml::from_str("default:\n  telegram: -1\n")?;
+        let sender = CompositeSender::new(vec![Box::new(FileSender::new(path.clone()))], channels);
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+        let deliveries = sender.deliver(&target, "Found match: Curator".to_string(), &target.uri);
+        assert_eq!(deliveries, vec![("file".to_string(), Ok(()))]);
+        let log = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        assert!(log.ends_with(" [to default] https://museum.org/jobs: Found match: Curator\n"));
+        Ok(())
+    }
+}
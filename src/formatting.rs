@@ -0,0 +1,93 @@
+// Formatting of alerts shared by the senders, e.g., the HTML of email and matrix messages or the
+// truncation of the fields of slack and discord messages.
+
+use crate::myscraper::Target;
+
+// Returns the HTML body of the alert, msg is kept preformatted (e.g., diffs) and followed by link,
+// the link to the match.
+pub fn html(msg: &str, link: &str) -> String {
+    format!(
+        "<pre>{}</pre>\n<p><a href=\"{}\">{}</a></p>\n",
+        escape_html(msg),
+        escape_html(link),
+        escape_html(link)
+    )
+}
+
+// Returns the text of the alert msg for backends without a subject or title, which is prefixed with
+// the uri of the target. Messages rendered from a message template are left as they are, the
+// template has the {uri} if it's wanted.
+pub fn text(target: &Target, msg: &str) -> String {
+    match target.message_template {
+        Some(_) => msg.to_string(),
+        None => format!("{}: {}", target.uri, msg),
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Returns the first max_chars chars of s, ending with "…" if s is longer.
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+    let mut truncated: String = s.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::Template;
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            html(
+                "Found match: <b>Curator</b> & \"Registrar's\"",
+                "https://museum.org/jobs?a=1&b=2"
+            ),
+            "<pre>Found match: &lt;b&gt;Curator&lt;/b&gt; &amp; &quot;Registrar&#39;s&quot;</pre>\n\
+             <p><a href=\"https://museum.org/jobs?a=1&amp;b=2\">https://museum.org/jobs?a=1&amp;b=2</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_text() -> Result<(), Box<dyn std::error::Error>> {
+        let mut target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            text(&target, "Found match: Curator"),
+            "https://museum.org/jobs: Found match: Curator"
+        );
+        target.message_template = Some(Template::parse("{match} at {link}")?);
+        assert_eq!(
+            text(&target, "Curator at https://museum.org/jobs/1"),
+            "Curator at https://museum.org/jobs/1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Curator", 7), "Curator");
+        assert_eq!(truncate("Curator", 4), "Cur…");
+        assert_eq!(truncate("Café curator", 5), "Café…");
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::error::LmkError;
+use crate::myscraper::{Sender, Target};
+use crate::notify::{self, send_with_retries};
+
+// Pushes messages to a self-hosted Gotify server, addr is the url of the server, e.g.,
+// https://gotify.museum.org. Requires the token of a Gotify application being in the GOTIFY_TOKEN
+// env variable.
+pub struct GotifySender {
+    client: reqwest::blocking::Client,
+    token: String,
+    retry_delay: Duration,
+}
+
+impl Sender for GotifySender {
+    fn name(&self) -> String {
+        "gotify".to_string()
+    }
+
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        let request = self
+            .client
+            .post(format!("{}/message", addr.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.token)
+            .json(&payload(target, &msg, link));
+        send_with_retries(request, "gotify", self.retry_delay)
+    }
+}
+
+impl GotifySender {
+    pub fn new() -> Result<Self, LmkError> {
+        let token = std::env::var("GOTIFY_TOKEN")
+            .map_err(|_| LmkError::Config("GOTIFY_TOKEN isn't set".to_string()))?;
+        Self::with_token(token, notify::DEFAULT_RETRY_DELAY)
+    }
+
+    fn with_token(token: String, retry_delay: Duration) -> Result<Self, LmkError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(notify::TIMEOUT)
+            .build()
+            .map_err(|e| LmkError::Config(format!("gotify: {}", e)))?;
+        Ok(GotifySender {
+            client,
+            token,
+            retry_delay,
+        })
+    }
+}
+
+// Returns the message pushed for msg, clicking the notification opens the match link.
+fn payload(target: &Target, msg: &str, link: &str) -> Value {
+    let title = if target.text.is_empty() {
+        &target.uri
+    } else {
+        &target.text
+    };
+    // Gotify has no tags, they're appended as hashtags.
+    let mut message = msg.to_string();
+    if !target.tags.is_empty() {
+        let tags: Vec<_> = target.tags.iter().map(|x| format!("#{}", x)).collect();
+        message = format!("{}\n\n{}", message, tags.join(" "));
+    }
+    json!({
+        "title": title,
+        "message": message,
+        "priority": gotify_priority(target.priority()),
+        "extras": {
+            "client::notification": {"click": {"url": link}},
+        },
+    })
+}
+
+// Maps priorities from 1 (min) to 5 (max) to the 0-10 priorities of Gotify, whose clients are
+// silent below 4 and pop up from 8 on.
+fn gotify_priority(priority: u8) -> u8 {
+    match priority {
+        0 | 1 => 1,
+        2 => 3,
+        3 => 5,
+        4 => 8,
+        _ => 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::matchers::{eq, json_decoded, request};
+    use httptest::responders::status_code;
+    use httptest::{all_of, Expectation};
+
+    #[test]
+    fn test_gotify_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/gotify/message"),
+                request::headers(httptest::matchers::contains(("x-gotify-key", "app-token"))),
+                request::body(json_decoded(eq(json!({
+                    "title": "Curator",
+                    "message": "Found match: Curator\n\n#art #nyc",
+                    "priority": 10,
+                    "extras": {
+                        "client::notification": {
+                            "click": {"url": "https://museum.org/jobs"},
+                        },
+                    },
+                })))),
+            ])
+            .respond_with(status_code(200)),
+        );
+        let sender = GotifySender::with_token("app-token".to_string(), Duration::ZERO)?;
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            priority: Some(5),
+            tags: vec!["art".to_string(), "nyc".to_string()],
+            ..Default::default()
+        };
+        sender.send(
+            &server.url_str("/gotify/"),
+            &target,
+            "Found match: Curator".to_string(),
+            &target.uri,
+        )?;
+
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/gotify/message"))
+                .respond_with(status_code(401)),
+        );
+        assert_eq!(
+            sender.send(
+                &server.url_str("/gotify"),
+                &target,
+                "x".to_string(),
+                &target.uri
+            ),
+            Err(LmkError::Notify("gotify: 401 Unauthorized".to_string()))
+        );
+        Ok(())
+    }
+}
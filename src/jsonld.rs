@@ -0,0 +1,333 @@
+// Extraction of schema.org JobPosting objects embedded in html pages.
+//
+// Careers pages often describe their postings with json-ld
+// (<script type="application/ld+json">) or microdata (itemscope/itemprop attributes), both are
+// turned into `Posting`s here so they can be matched and reported like job board postings.
+
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+
+use crate::boards::Posting;
+
+// Returns all the JobPostings found in json-ld scripts or microdata of `page`.
+pub fn extract_job_postings(page: &Html) -> Vec<Posting> {
+    let mut postings = vec![];
+    let scripts = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    for script in page.select(&scripts) {
+        let text: String = script.text().collect();
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => collect_json_ld(&value, &mut postings),
+            Err(e) => log::debug!("skipping invalid json-ld script: {}", e),
+        }
+    }
+    let scopes = Selector::parse("[itemscope][itemtype]").unwrap();
+    postings.extend(
+        page.select(&scopes)
+            .filter(|e| is_job_posting_type(e.value().attr("itemtype").unwrap_or_default()))
+            .map(microdata_posting),
+    );
+    postings
+}
+
+// Returns the text of the json-ld scripts of page, the source of the postings rather than content
+// of the page.
+pub fn json_ld_sources(page: &Html) -> Vec<&str> {
+    let scripts = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    page.select(&scripts).flat_map(|x| x.text()).collect()
+}
+
+// Types can be written as "JobPosting", "schema:JobPosting" or "https://schema.org/JobPosting".
+fn is_job_posting_type(t: &str) -> bool {
+    t.split_whitespace().any(|t| t.ends_with("JobPosting"))
+}
+
+// Walks a json-ld value, json-ld documents can be a single object, a list of objects or a
+// "@graph" of objects.
+fn collect_json_ld(value: &Value, postings: &mut Vec<Posting>) {
+    match value {
+        Value::Array(values) => values.iter().for_each(|v| collect_json_ld(v, postings)),
+        Value::Object(object) => {
+            let is_posting = match object.get("@type") {
+                Some(Value::String(t)) => is_job_posting_type(t),
+                Some(Value::Array(types)) => types
+                    .iter()
+                    .any(|t| t.as_str().is_some_and(is_job_posting_type)),
+                _ => false,
+            };
+            if is_posting {
+                postings.push(json_ld_posting(value));
+            } else if let Some(graph) = object.get("@graph") {
+                collect_json_ld(graph, postings);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn json_ld_posting(value: &Value) -> Posting {
+    let field = |name: &str| value.get(name).map(json_text).unwrap_or_default();
+    let mut location = value
+        .get("jobLocation")
+        .map(json_location)
+        .unwrap_or_default();
+    if location.is_empty() && field("jobLocationType") == "TELECOMMUTE" {
+        location = "Remote".into();
+    }
+    let mut posting = Posting {
+        title: field("title"),
+        department: field("occupationalCategory"),
+        location,
+        url: field("url"),
+        date_posted: field("datePosted"),
+        valid_through: field("validThrough"),
+        salary: value.get("baseSalary").map(json_salary).unwrap_or_default(),
+        ..Default::default()
+    };
+    posting.id = match value.get("identifier") {
+        Some(Value::Object(identifier)) => identifier.get("value").map(json_text),
+        Some(identifier) => Some(json_text(identifier)),
+        None => None,
+    }
+    .filter(|id| !id.is_empty())
+    .unwrap_or_else(|| default_id(&posting));
+    posting
+}
+
+// Postings without an explicit identifier are keyed by their url, or title if there's no url.
+fn default_id(posting: &Posting) -> String {
+    if posting.url.is_empty() {
+        posting.title.clone()
+    } else {
+        posting.url.clone()
+    }
+}
+
+// Returns the human readable text of a json value. Objects are represented by their name.
+fn json_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Array(values) => values
+            .iter()
+            .map(json_text)
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Object(object) => object.get("name").map(json_text).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+// jobLocation is a Place (or list of them) whose address is a PostalAddress or plain text.
+fn json_location(value: &Value) -> String {
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .map(json_location)
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("; "),
+        Value::Object(place) => match place.get("address") {
+            Some(Value::Object(address)) => ["addressLocality", "addressRegion", "addressCountry"]
+                .iter()
+                .filter_map(|k| address.get(*k).map(json_text))
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Some(address) => json_text(address),
+            None => json_text(value),
+        },
+        _ => json_text(value),
+    }
+}
+
+// baseSalary is a MonetaryAmount whose value is a number or a QuantitativeValue, e.g.,
+// {"currency": "USD", "value": {"minValue": 50000, "maxValue": 60000, "unitText": "YEAR"}}
+fn json_salary(value: &Value) -> String {
+    let amount = match value.get("value") {
+        Some(Value::Object(quantity)) => {
+            let amount = match (quantity.get("minValue"), quantity.get("maxValue")) {
+                (Some(min), Some(max)) => format!("{}-{}", json_text(min), json_text(max)),
+                _ => quantity.get("value").map(json_text).unwrap_or_default(),
+            };
+            match quantity.get("unitText").map(json_text) {
+                Some(unit) if !unit.is_empty() && !amount.is_empty() => {
+                    format!("{}/{}", amount, unit)
+                }
+                _ => amount,
+            }
+        }
+        Some(amount) => json_text(amount),
+        None => return json_text(value),
+    };
+    match value.get("currency").map(json_text) {
+        Some(currency) if !currency.is_empty() && !amount.is_empty() => {
+            format!("{} {}", currency, amount)
+        }
+        _ => amount,
+    }
+}
+
+fn microdata_posting(scope: ElementRef) -> Posting {
+    let mut props = vec![];
+    collect_microdata_props(scope, &mut props);
+    let prop = |name: &str| {
+        props
+            .iter()
+            .find(|e| {
+                e.value()
+                    .attr("itemprop")
+                    .is_some_and(|x| x.split_whitespace().any(|x| x == name))
+            })
+            .map(|e| microdata_value(*e))
+            .unwrap_or_default()
+    };
+    let mut posting = Posting {
+        title: prop("title"),
+        department: prop("occupationalCategory"),
+        location: prop("jobLocation"),
+        url: prop("url"),
+        date_posted: prop("datePosted"),
+        valid_through: prop("validThrough"),
+        salary: prop("baseSalary"),
+        ..Default::default()
+    };
+    posting.id = Some(prop("identifier"))
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| default_id(&posting));
+    posting
+}
+
+// Collects the itemprop elements of the item of element, in document order. The properties of
+// nested items (e.g., the url of the hiringOrganization) belong to those items, so nested itemscope
+// elements are collected but not descended into.
+fn collect_microdata_props<'a>(element: ElementRef<'a>, props: &mut Vec<ElementRef<'a>>) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        if child.value().attr("itemprop").is_some() {
+            props.push(child);
+        }
+        if child.value().attr("itemscope").is_none() {
+            collect_microdata_props(child, props);
+        }
+    }
+}
+
+// Microdata values live in the content/href/datetime attributes, falling back to the text.
+fn microdata_value(element: ElementRef) -> String {
+    let e = element.value();
+    match e
+        .attr("content")
+        .or_else(|| e.attr("href"))
+        .or_else(|| e.attr("datetime"))
+    {
+        Some(value) => value.trim().to_string(),
+        None => element
+            .text()
+            .flat_map(|x| x.split_whitespace())
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_json_ld() {
+        let page = Html::parse_document(
+            r#"
+            <html><head>
+            <script type="application/ld+json">
+            {
+                "@context": "https://schema.org/",
+                "@type": "JobPosting",
+                "title": "Assistant Curator",
+                "identifier": {"@type": "PropertyValue", "name": "Museum", "value": "AC-1"},
+                "datePosted": "2022-10-01",
+                "validThrough": "2022-11-01T00:00",
+                "url": "https://museum.org/jobs/ac-1",
+                "jobLocation": {
+                    "@type": "Place",
+                    "address": {
+                        "@type": "PostalAddress",
+                        "addressLocality": "Brooklyn",
+                        "addressRegion": "NY",
+                        "addressCountry": "US"
+                    }
+                },
+                "baseSalary": {
+                    "@type": "MonetaryAmount",
+                    "currency": "USD",
+                    "value": {"@type": "QuantitativeValue", "minValue": 50000, "maxValue": 60000, "unitText": "YEAR"}
+                }
+            }
+            </script>
+            <script type="application/ld+json">
+            {"@graph": [{"@type": "WebPage"}, {"@type": ["JobPosting"], "title": "Registrar", "jobLocationType": "TELECOMMUTE"}]}
+            </script>
+            <script type="application/ld+json">{ not json </script>
+            </head></html>
+            "#,
+        );
+        let postings = extract_job_postings(&page);
+        assert_eq!(
+            postings,
+            vec![
+                Posting {
+                    id: "AC-1".into(),
+                    title: "Assistant Curator".into(),
+                    location: "Brooklyn, NY, US".into(),
+                    url: "https://museum.org/jobs/ac-1".into(),
+                    date_posted: "2022-10-01".into(),
+                    valid_through: "2022-11-01T00:00".into(),
+                    salary: "USD 50000-60000/YEAR".into(),
+                    ..Default::default()
+                },
+                Posting {
+                    id: "Registrar".into(),
+                    title: "Registrar".into(),
+                    location: "Remote".into(),
+                    ..Default::default()
+                }
+            ]
+        );
+        assert_eq!(
+            postings[0].summary(),
+            "Assistant Curator (Brooklyn, NY, US, USD 50000-60000/YEAR) https://museum.org/jobs/ac-1 apply by 2022-11-01T00:00"
+        );
+    }
+
+    #[test]
+    fn test_extract_microdata() {
+        let page = Html::parse_document(
+            r#"
+            <div itemscope itemtype="https://schema.org/JobPosting">
+              <div itemprop="hiringOrganization" itemscope itemtype="https://schema.org/Organization">
+                <a itemprop="url" href="https://sculpture-center.org">SculptureCenter</a>
+                <span itemprop="title">Museum</span>
+              </div>
+              <h2 itemprop="title name">Curatorial Assistant</h2>
+              <meta itemprop="datePosted" content="2022-09-30">
+              <div itemprop="jobLocation" itemscope itemtype="https://schema.org/Place">
+                <span itemprop="address">Long Island City,
+                  NY</span>
+              </div>
+              <a itemprop="url" href="https://sculpture-center.org/jobs/1">apply</a>
+            </div>
+            <div itemscope itemtype="https://schema.org/Event"><span itemprop="title">Gala</span></div>
+            "#,
+        );
+        assert_eq!(
+            extract_job_postings(&page),
+            vec![Posting {
+                id: "https://sculpture-center.org/jobs/1".into(),
+                title: "Curatorial Assistant".into(),
+                location: "Long Island City, NY".into(),
+                url: "https://sculpture-center.org/jobs/1".into(),
+                date_posted: "2022-09-30".into(),
+                ..Default::default()
+            }]
+        );
+    }
+}
@@ -1,46 +1,219 @@
+use crate::channel::Channels;
+use crate::discordsender::DiscordSender;
+use crate::emailsender::{EmailSender, SmtpSecurity};
+use crate::error::LmkError;
+use crate::fetcher::ReqwestFetcher;
+use crate::filesender::FileSender;
+use crate::gotifysender::GotifySender;
+use crate::matrixsender::MatrixSender;
 use crate::myscraper::Target;
-use crate::telegramsender::TelegramSender;
+use crate::ntfysender::NtfySender;
+use crate::session::Session;
+use crate::slacksender::SlackSender;
+use crate::telegramsender::{TelegramFormat, TelegramSender};
+use crate::template::Template;
+use crate::webhooksender::WebhookSender;
 
-use clap::Parser;
-use myscraper::PrintSender;
+use clap::{Parser, ValueEnum};
+use fetcher::Renderer;
+use myscraper::{CompositeSender, FetchOptions, PrintSender, Scraper, Sender};
 use opentelemetry::sdk::export::trace::stdout;
 use scoped_timer::ScopedTimer;
+use serde::Deserialize;
+use warc::WarcWriter;
 
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
+mod boards;
+mod channel;
+mod crawl;
 mod db;
+mod diff;
+mod discordsender;
+mod emailsender;
+mod error;
+mod fetcher;
+mod filesender;
+mod formatting;
+mod gotifysender;
+mod jsonld;
+mod matrixsender;
 mod myscraper;
+mod notify;
+mod ntfysender;
+mod pdf;
+mod record;
 mod scoped_timer;
+mod session;
+mod slacksender;
 mod telegramsender;
+mod template;
+mod threshold;
+mod warc;
+mod webhooksender;
 
-// TODO: this is unused because I couldn't figure out how to make the reporting flag turn into a nenum.
-#[derive(PartialEq, Debug)]
+// Where matches are reported, see the --reporting flag.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
 pub enum Reporting {
-    // Use a telegramsender::TelegramSender to report matches.
-    // The telegram_chat_id defines which chat to use, note that this
-    // requires that the telegram token is in scope.
-    Telegram,
-    // Just print matches to stdout
+    /// Just print matches to stdout
     Print,
+    /// Send matches to the telegram chats of the notification channels (--telegram-chat-id by
+    /// default), requires TELOXIDE_TOKEN being set
+    Telegram,
+    /// Append matches to the --report-file log file
+    File,
+    /// Email matches to the addresses of the notification channels (--email-to by default)
+    /// through the --smtp-host server
+    Email,
+    /// POST matches to the webhooks of the notification channels (--webhook-url by default)
+    Webhook,
+    /// Post matches to the Slack incoming webhooks of the notification channels
+    /// (--slack-webhook-url by default)
+    Slack,
+    /// Post matches to the Discord webhooks of the notification channels
+    /// (--discord-webhook-url by default)
+    Discord,
+    /// Push matches to the ntfy topics of the notification channels (--ntfy-topic-url by
+    /// default)
+    Ntfy,
+    /// Push matches to the Gotify servers of the notification channels (--gotify-url by
+    /// default), requires GOTIFY_TOKEN being set
+    Gotify,
+    /// Post matches to the Matrix rooms of the notification channels (--matrix-room by default)
+    /// through the --matrix-homeserver, requires MATRIX_ACCESS_TOKEN being set
+    Matrix,
+}
+
+impl Reporting {
+    // Creates the sender that reports matches to this backend.
+    fn new_sender(self, args: &Args) -> Result<Box<dyn Sender + Send + Sync>, LmkError> {
+        Ok(match self {
+            Reporting::Print => Box::new(PrintSender {}),
+            Reporting::Telegram => Box::new(TelegramSender::new(
+                args.telegram_format,
+                args.telegram_disable_link_preview,
+            )?),
+            Reporting::File => Box::new(FileSender::new(args.report_file.clone())),
+            Reporting::Email => {
+                let (Some(host), Some(from)) = (&args.smtp_host, &args.smtp_from) else {
+                    return Err(LmkError::Config(
+                        "--reporting email needs --smtp-host and --smtp-from".to_string(),
+                    ));
+                };
+                Box::new(EmailSender::new(
+                    host,
+                    args.smtp_port,
+                    args.smtp_security,
+                    from,
+                    EmailSender::credentials_from_env()?,
+                )?)
+            }
+            Reporting::Webhook => Box::new(WebhookSender::new(args.webhook_template.as_deref())?),
+            Reporting::Slack => Box::new(SlackSender::new()?),
+            Reporting::Discord => Box::new(DiscordSender::new()?),
+            Reporting::Ntfy => Box::new(NtfySender::new()?),
+            Reporting::Gotify => Box::new(GotifySender::new()?),
+            Reporting::Matrix => {
+                let Some(homeserver) = &args.matrix_homeserver else {
+                    return Err(LmkError::Config(
+                        "--reporting matrix needs --matrix-homeserver".to_string(),
+                    ));
+                };
+                Box::new(MatrixSender::new(homeserver)?)
+            }
+        })
+    }
 }
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Type of reporting app should do:
-    ///  "print" -> just print results
-    ///  "telegram" -> use telegram chat (requires telegram_chat_id being set)
-    #[arg(short, long)]
-    reporting: String,
+    /// Where matches are reported, can be given multiple times to report to several backends,
+    /// e.g., --reporting print --reporting telegram
+    #[arg(short, long, value_enum, required = true)]
+    reporting: Vec<Reporting>,
 
-    /// Telegram Chat ID
+    /// Telegram Chat ID of the default notification channel, unless the targets file sets one.
     /// Defaults to bilal's bot.
     #[arg(short, long, default_value_t = -727046961)]
     telegram_chat_id: i64,
 
+    /// How telegram messages are formatted.
+    #[arg(long, value_enum, default_value_t = TelegramFormat::Html)]
+    telegram_format: TelegramFormat,
+
+    /// Don't show previews of the links in telegram messages.
+    #[arg(long)]
+    telegram_disable_link_preview: bool,
+
+    /// Log file that matches are appended to with --reporting file.
+    #[arg(long, default_value = "lmk-alerts.log")]
+    report_file: PathBuf,
+
+    /// SMTP server that --reporting email sends through. The credentials are read from the
+    /// SMTP_USERNAME and SMTP_PASSWORD env variables.
+    #[arg(long)]
+    smtp_host: Option<String>,
+
+    /// Port of the SMTP server, defaults to the port of --smtp-security.
+    #[arg(long)]
+    smtp_port: Option<u16>,
+
+    /// How the connection to the SMTP server is secured.
+    #[arg(long, value_enum, default_value_t = SmtpSecurity::Starttls)]
+    smtp_security: SmtpSecurity,
+
+    /// Address alert emails are sent from, e.g., "lmk <lmk@museum.org>".
+    #[arg(long)]
+    smtp_from: Option<String>,
+
+    /// Email address of the default notification channel, unless the targets file sets one.
+    #[arg(long)]
+    email_to: Option<String>,
+
+    /// Webhook url of the default notification channel, unless the targets file sets one.
+    #[arg(long)]
+    webhook_url: Option<String>,
+
+    /// JSON file of the payload --reporting webhook posts, its strings are message templates,
+    /// e.g., "{uri}", "{text}", "{link}" and "{message}" are replaced by the target uri, the
+    /// text looked for, the link to the match and the alert. Defaults to
+    /// {"text": "{uri}: {message}"}.
+    #[arg(long)]
+    webhook_template: Option<PathBuf>,
+
+    /// Slack incoming webhook url of the default notification channel, unless the targets file
+    /// sets one.
+    #[arg(long)]
+    slack_webhook_url: Option<String>,
+
+    /// Discord webhook url of the default notification channel, unless the targets file sets one.
+    #[arg(long)]
+    discord_webhook_url: Option<String>,
+
+    /// ntfy topic url of the default notification channel, e.g., https://ntfy.sh/museum-jobs,
+    /// unless the targets file sets one. Protected topics need NTFY_TOKEN being set.
+    #[arg(long)]
+    ntfy_topic_url: Option<String>,
+
+    /// Gotify server url of the default notification channel, unless the targets file sets one.
+    #[arg(long)]
+    gotify_url: Option<String>,
+
+    /// Matrix homeserver that --reporting matrix posts through, e.g., https://matrix.org.
+    #[arg(long)]
+    matrix_homeserver: Option<String>,
+
+    /// Matrix room id or alias of the default notification channel, e.g., #jobs:matrix.org,
+    /// unless the targets file sets one.
+    #[arg(long)]
+    matrix_room: Option<String>,
+
     /// Scraper Build ID -- git short commit ID of the version that this scraper ran as.
     /// useful for figuring out what version ran etc...
     #[arg(long)]
@@ -50,34 +223,192 @@ struct Args {
     /// the stdout
     #[arg(long, default_value_t = false)]
     jaeger_tracing: bool,
+
+    /// Test mode: instead of fetching the targets, the page read from stdin is used as the
+    /// content of every target. The target cache is kept in memory so it isn't affected.
+    /// e.g., $ lmk --reporting print --stdin < saved-careers-page.html
+    #[arg(long, default_value_t = false)]
+    stdin: bool,
+
+    /// Stores every fetched response under <RECORD>/<run timestamp>/ so that the run can be
+    /// reproduced with --replay.
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Serves the responses recorded by --record in the given run directory instead of fetching
+    /// them. The target cache is kept in memory so it isn't affected.
+    #[arg(long, conflicts_with_all = ["record", "stdin"])]
+    replay: Option<PathBuf>,
+
+    /// Archives every fetched response as WARC records in the given directory, the ids of the
+    /// records a match was found in are kept in the match history of the target cache.
+    #[arg(long)]
+    warc_dir: Option<PathBuf>,
+
+    /// Size in bytes after which a new WARC file is started.
+    #[arg(long, default_value_t = 1 << 30)]
+    warc_max_bytes: u64,
+
+    /// Endpoint of the rendering service that targets with `render: true` are fetched through,
+    /// e.g., http://localhost:3000/content for a browserless container.
+    #[arg(long)]
+    renderer_uri: Option<String>,
+}
+
+// The contents of the targets file. It's either just the list of targets, or a map that holds
+// the targets along with the sessions and notification channels they use.
+#[derive(Deserialize, Debug, Default)]
+struct Config {
+    targets: Vec<Target>,
+    // Login sessions by name, see session::Session.
+    #[serde(default)]
+    sessions: HashMap<String, Session>,
+    // Notification channels by name, see channel::Channels.
+    #[serde(default)]
+    channels: Channels,
+    // Template of the messages of the targets without one, see template::Template.
+    #[serde(default)]
+    message_template: Option<Template>,
 }
 
-fn read_targets<P: AsRef<Path>>(path: P) -> Result<Vec<Target>, Box<dyn std::error::Error>> {
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigFile {
+    Targets(Vec<Target>),
+    Config(Config),
+}
+
+fn read_config<P: AsRef<Path>>(path: P) -> Result<Config, LmkError> {
+    let path = path.as_ref();
     // Open the file in read-only mode with buffer.
-    let file = File::open(path)?;
+    let file =
+        File::open(path).map_err(|e| LmkError::Config(format!("{}: {}", path.display(), e)))?;
     let reader = BufReader::new(file);
 
-    let targets = serde_yaml::from_reader(reader)?;
+    let mut config = match serde_yaml::from_reader(reader)
+        .map_err(|e| LmkError::Config(format!("{}: {}", path.display(), e)))?
+    {
+        ConfigFile::Targets(targets) => Config {
+            targets,
+            ..Default::default()
+        },
+        ConfigFile::Config(config) => config,
+    };
+    config
+        .channels
+        .validate()
+        .map_err(|e| LmkError::Config(format!("{}: {}", path.display(), e)))?;
+    for t in &config.targets {
+        t.validate()?;
+        if !t.session.is_empty() && !config.sessions.contains_key(&t.session) {
+            return Err(LmkError::Config(format!(
+                "target {} uses unknown session {:?}",
+                t.uri, t.session
+            )));
+        }
+        if t.priority.is_some_and(|x| !(1..=5).contains(&x)) {
+            return Err(LmkError::Config(format!(
+                "target {} has priority {}, priorities go from 1 to 5",
+                t.uri,
+                t.priority.unwrap_or_default()
+            )));
+        }
+        if let Some(x) = t
+            .channels
+            .iter()
+            .find(|x| *x != Channels::DEFAULT && !config.channels.contains(x))
+        {
+            return Err(LmkError::Config(format!(
+                "target {} uses unknown channel {:?}",
+                t.uri, x
+            )));
+        }
+    }
 
-    Ok(targets)
+    if let Some(template) = &config.message_template {
+        for t in config.targets.iter_mut() {
+            t.message_template.get_or_insert_with(|| template.clone());
+        }
+    }
+    Ok(config)
 }
 
 const TARGETS_PATH: &str = "targets.yaml";
+const DB_PATH: &str = "./.scraper_target_cache.db";
+
+// Creates a scraper of targets with the fetch options of args.
+fn new_scraper<'a, S: Sender>(
+    config: Config,
+    sender: &'a S,
+    args: &Args,
+) -> Result<Scraper<'a, S>, LmkError> {
+    let mut options = FetchOptions {
+        sessions: config.sessions,
+        ..FetchOptions::new(ReqwestFetcher::new(ReqwestFetcher::DEFAULT_TIMEOUT)?)
+    };
+    if args.stdin {
+        let mut body = String::new();
+        std::io::stdin()
+            .read_to_string(&mut body)
+            .map_err(|e| LmkError::Fetch(format!("stdin: {}", e)))?;
+        options.stdin = Some(body);
+    }
+    if let Some(dir) = &args.record {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| LmkError::Config(e.to_string()))?
+            .as_secs();
+        let run_dir = dir.join(now.to_string());
+        log::info!("recording responses to {:?}", run_dir);
+        options.record = Some(run_dir);
+    }
+    options.replay = args.replay.clone();
+    if let Some(dir) = &args.warc_dir {
+        options.warc = Some(WarcWriter::new(dir.clone(), args.warc_max_bytes));
+    }
+    match &args.renderer_uri {
+        Some(endpoint) => {
+            options.renderer = Some(Renderer {
+                endpoint: endpoint.clone(),
+            })
+        }
+        None => {
+            if let Some(t) = config.targets.iter().find(|t| t.render) {
+                return Err(LmkError::Config(format!(
+                    "target {} needs --renderer-uri to render",
+                    t.uri
+                )));
+            }
+        }
+    }
+    // Runs on saved content shouldn't affect the real target cache.
+    let db_path = if options.stdin.is_some() || options.replay.is_some() {
+        ":memory:"
+    } else {
+        DB_PATH
+    };
+    Scraper::new(config.targets, sender, db_path, options)
+}
 
 use opentelemetry::trace::{TraceContextExt, Tracer};
 use opentelemetry::{global, KeyValue};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     pretty_env_logger::init();
     let args = Args::parse();
-    let build_id = args.build_id.unwrap_or("none".into());
+    let build_id = args.build_id.clone().unwrap_or("none".into());
     log::info!("starting build_id {}...", build_id);
     // jaeger tracing
     if args.jaeger_tracing {
         global::set_text_map_propagator(opentelemetry_jaeger::Propagator::new());
-        let _tracer = opentelemetry_jaeger::new_agent_pipeline()
+        let tracer = opentelemetry_jaeger::new_agent_pipeline()
             .with_service_name("JobScraper")
-            .install_simple()?;
+            .install_simple();
+        if let Err(e) = tracer {
+            let e = LmkError::Config(format!("jaeger tracing: {}", e));
+            eprintln!("{}", e);
+            return ExitCode::from(e.exit_code());
+        }
     } else {
         let _tracer = stdout::new_pipeline()
             .with_pretty_print(true)
@@ -86,36 +417,68 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let tracer = global::tracer("scraper");
 
-    tracer.in_span("scrape-main", |cx| {
-        let targets = read_targets(TARGETS_PATH)?;
+    let result = tracer.in_span("scrape-main", |cx| {
+        let mut config = read_config(TARGETS_PATH)?;
         cx.span().set_attribute(KeyValue::new("build_id", build_id));
         cx.span()
             .set_attribute(KeyValue::new("targets_path", TARGETS_PATH));
-        cx.span()
-            .set_attribute(KeyValue::new("scrape-type", args.reporting.clone()));
-        match args.reporting.as_str() {
-            "print" => {
-                let _timer = ScopedTimer::new("print scrape time".into());
-                let sender = PrintSender {};
-                let s = myscraper::Scraper::new(targets, &sender);
-                s.scrape()
+        // Each backend reports once, even if it's given multiple times.
+        let mut reporting: Vec<Reporting> = vec![];
+        for x in &args.reporting {
+            if !reporting.contains(x) {
+                reporting.push(*x);
             }
-            "telegram" => {
-                let _timer = ScopedTimer::new("telegram scrape time".into());
-                let sender = TelegramSender::new(args.telegram_chat_id).unwrap();
-                let s = myscraper::Scraper::new(targets, &sender);
-                s.scrape()
-            }
-            // TODO(bilal): return an actual error here..
-            _ => todo!(
-                "Unsupported flag value for reporting {}, only 'print|telegram' supported.",
-                args.reporting
-            ),
         }
-    })?;
+        let scrape_type = reporting
+            .iter()
+            .filter_map(|x| x.to_possible_value())
+            .map(|x| x.get_name().to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        cx.span()
+            .set_attribute(KeyValue::new("scrape-type", scrape_type.clone()));
+        let _timer = ScopedTimer::new(format!("{} scrape time", scrape_type));
+        let senders = reporting
+            .into_iter()
+            .map(|x| x.new_sender(&args))
+            .collect::<Result<_, _>>()?;
+        let mut channels = std::mem::take(&mut config.channels);
+        channels.set_default_addr("telegram", args.telegram_chat_id.to_string());
+        if let Some(to) = &args.email_to {
+            channels.set_default_addr("email", to.clone());
+        }
+        if let Some(url) = &args.webhook_url {
+            channels.set_default_addr("webhook", url.clone());
+        }
+        if let Some(url) = &args.slack_webhook_url {
+            channels.set_default_addr("slack", url.clone());
+        }
+        if let Some(url) = &args.discord_webhook_url {
+            channels.set_default_addr("discord", url.clone());
+        }
+        if let Some(url) = &args.ntfy_topic_url {
+            channels.set_default_addr("ntfy", url.clone());
+        }
+        if let Some(url) = &args.gotify_url {
+            channels.set_default_addr("gotify", url.clone());
+        }
+        if let Some(room) = &args.matrix_room {
+            channels.set_default_addr("matrix", room.clone());
+        }
+        let sender = CompositeSender::new(senders, channels);
+        let s = new_scraper(config, &sender, &args)?;
+        s.scrape()
+    });
     // Shutdown trace pipeline
     global::shutdown_tracer_provider();
-    Ok(())
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            eprintln!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
 
 #[cfg(test)]
@@ -123,7 +486,189 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_serialze_targets() -> Result<(), Box<dyn std::error::Error>> {
-        read_targets(TARGETS_PATH).map(|_| ())
+    fn test_parse_reporting() {
+        let args = Args::try_parse_from(["lmk", "--reporting", "print", "-r", "telegram"]).unwrap();
+        assert_eq!(args.reporting, vec![Reporting::Print, Reporting::Telegram]);
+        let e = Args::try_parse_from(["lmk", "--reporting", "pigeon"]).unwrap_err();
+        assert_eq!(e.kind(), clap::error::ErrorKind::InvalidValue);
+        assert!(e.to_string().contains(
+            "possible values: print, telegram, file, email, webhook, slack, discord, ntfy, gotify, matrix"
+        ));
+        assert!(Args::try_parse_from(["lmk"]).is_err());
+    }
+
+    #[test]
+    fn test_serialze_targets() -> Result<(), LmkError> {
+        read_config(TARGETS_PATH).map(|_| ())
+    }
+
+    #[test]
+    fn test_read_config_sessions() -> Result<(), Box<dyn std::error::Error>> {
+        let path =
+            std::env::temp_dir().join(format!("lmk-test-config-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+sessions:
+  members:
+    login_uri: https://museum.org/login
+    form:
+      password: ${MUSEUM_PASSWORD}
+targets:
+  - uri: https://museum.org/members/jobs
+    text: Curator
+    session: members
+"#,
+        )?;
+        let config = read_config(&path)?;
+        assert_eq!(config.targets[0].session, "members");
+        assert_eq!(
+            config.sessions["members"].login_uri,
+            "https://museum.org/login"
+        );
+
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/jobs\n  text: Curator\n  session: members\n",
+        )?;
+        assert!(read_config(&path).is_err());
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_config_channels() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "lmk-test-config-channels-{}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            r#"
+channels:
+  contemporary:
+    telegram: -100123
+targets:
+  - uri: https://museum.org/jobs
+    text: Curator
+    channels: [contemporary, default]
+"#,
+        )?;
+        let config = read_config(&path)?;
+        assert_eq!(
+            config
+                .channels
+                .addrs(&config.targets[0].channels, "telegram"),
+            vec!["-100123"]
+        );
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/jobs\n  text: Curator\n  channels: [modern]\n",
+        )?;
+        assert_eq!(
+            read_config(&path).unwrap_err(),
+            LmkError::Config(
+                "target https://museum.org/jobs uses unknown channel \"modern\"".to_string()
+            )
+        );
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/jobs\n  text: Curator\n  priority: 6\n",
+        )?;
+        assert!(read_config(&path).is_err());
+        std::fs::write(
+            &path,
+            "channels:\n  modern:\n    telegram: modern\ntargets: []\n",
+        )?;
+        assert!(matches!(read_config(&path), Err(LmkError::Config(_))));
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_config_modes() -> Result<(), Box<dyn std::error::Error>> {
+        let path =
+            std::env::temp_dir().join(format!("lmk-test-config-modes-{}.yaml", std::process::id()));
+        // Diff and threshold mode targets don't need a text.
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/visitors\n  mode: threshold\n  selector: '#count'\n  regex: '(\\d+) visitors'\n",
+        )?;
+        assert_eq!(read_config(&path)?.targets[0].text, "");
+        std::fs::write(&path, "- uri: https://museum.org/jobs\n  mode: match\n")?;
+        assert!(matches!(read_config(&path), Err(LmkError::Config(e)) if e.contains("no text")));
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/visitors\n  mode: diff\n  selector: '#count['\n",
+        )?;
+        assert!(
+            matches!(read_config(&path), Err(LmkError::Config(e)) if e.contains("invalid selector"))
+        );
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/visitors\n  mode: threshold\n  regex: '(\\d+'\n",
+        )?;
+        assert!(
+            matches!(read_config(&path), Err(LmkError::Config(e)) if e.contains("invalid regex"))
+        );
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/jobs\n  kind: crawl\n  text: Curator\n  follow: '/jobs/(\\d+'\n",
+        )?;
+        assert!(
+            matches!(read_config(&path), Err(LmkError::Config(e)) if e.contains("invalid follow regex"))
+        );
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/jobs\n  text: Curator\n  pdf_links: '*.pdf'\n",
+        )?;
+        assert!(
+            matches!(read_config(&path), Err(LmkError::Config(e)) if e.contains("invalid pdf_links regex"))
+        );
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/jobs\n  text: Curator\n  next_page: 'a.next['\n",
+        )?;
+        assert!(
+            matches!(read_config(&path), Err(LmkError::Config(e)) if e.contains("invalid next_page selector"))
+        );
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_config_message_template() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "lmk-test-config-message-template-{}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            r#"
+message_template: "{description}: {match}"
+targets:
+  - uri: https://museum.org/jobs
+    text: Curator
+  - uri: https://whitney.org/jobs
+    text: Curator
+    message_template: "{link}"
+"#,
+        )?;
+        let config = read_config(&path)?;
+        assert_eq!(
+            config.targets[0].message_template,
+            Some(Template::parse("{description}: {match}")?)
+        );
+        assert_eq!(
+            config.targets[1].message_template,
+            Some(Template::parse("{link}")?)
+        );
+        std::fs::write(
+            &path,
+            "- uri: https://museum.org/jobs\n  text: Curator\n  message_template: \"{title}\"\n",
+        )?;
+        assert!(matches!(read_config(&path), Err(LmkError::Config(_))));
+        std::fs::remove_file(path)?;
+        Ok(())
     }
 }
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::error::LmkError;
+use crate::formatting;
+use crate::myscraper::{Sender, Target};
+use crate::notify::{self, send_with_retries};
+
+// Posts messages to Matrix rooms through the client-server API of a homeserver, addr is the room
+// id or alias, e.g., !jobs:matrix.org or #jobs:matrix.org. Requires the access token of the
+// posting user being in the MATRIX_ACCESS_TOKEN env variable.
+pub struct MatrixSender {
+    client: reqwest::blocking::Client,
+    homeserver: Url,
+    token: String,
+    retry_delay: Duration,
+}
+
+impl Sender for MatrixSender {
+    fn name(&self) -> String {
+        "matrix".to_string()
+    }
+
+    fn send(&self, addr: &str, _target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        let room_id = if addr.starts_with('#') {
+            self.resolve_alias(addr)?
+        } else {
+            addr.to_string()
+        };
+        // The transaction id is kept across retries, so that the homeserver doesn't post the
+        // message twice if only its response got lost.
+        let txn_id = uuid::Uuid::new_v4().to_string();
+        let url = self.api_url(&["rooms", &room_id, "send", "m.room.message", &txn_id]);
+        let request = self
+            .client
+            .put(url)
+            .bearer_auth(&self.token)
+            .json(&payload(&msg, link));
+        send_with_retries(request, "matrix", self.retry_delay)
+    }
+}
+
+impl MatrixSender {
+    // Creates a new Sender that posts through the homeserver, e.g., https://matrix.org.
+    pub fn new(homeserver: &str) -> Result<Self, LmkError> {
+        let token = std::env::var("MATRIX_ACCESS_TOKEN")
+            .map_err(|_| LmkError::Config("MATRIX_ACCESS_TOKEN isn't set".to_string()))?;
+        Self::with_token(homeserver, token, notify::DEFAULT_RETRY_DELAY)
+    }
+
+    fn with_token(
+        homeserver: &str,
+        token: String,
+        retry_delay: Duration,
+    ) -> Result<Self, LmkError> {
+        let homeserver = Url::parse(homeserver).map_err(|e| {
+            LmkError::Config(format!("invalid matrix homeserver {:?}: {}", homeserver, e))
+        })?;
+        if homeserver.cannot_be_a_base() {
+            return Err(LmkError::Config(format!(
+                "invalid matrix homeserver {:?}",
+                homeserver.as_str()
+            )));
+        }
+        let client = reqwest::blocking::Client::builder()
+            .timeout(notify::TIMEOUT)
+            .build()
+            .map_err(|e| LmkError::Config(format!("matrix: {}", e)))?;
+        Ok(MatrixSender {
+            client,
+            homeserver,
+            token,
+            retry_delay,
+        })
+    }
+
+    // Returns the url of the client-server API endpoint at path, its segments are escaped, e.g.,
+    // the ! and : of room ids.
+    fn api_url(&self, path: &[&str]) -> Url {
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .expect("homeserver is a base url")
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(path);
+        url
+    }
+
+    // Returns the id of the room with the alias.
+    fn resolve_alias(&self, alias: &str) -> Result<String, LmkError> {
+        let url = self.api_url(&["directory", "room", alias]);
+        let response = self
+            .client
+            .get(url)
+            .bearer_auth(&self.token)
+            .send()
+            .and_then(|x| x.error_for_status())
+            .map_err(|e| LmkError::Notify(format!("matrix: resolving {}: {}", alias, e)))?;
+        let room: Value = response
+            .json()
+            .map_err(|e| LmkError::Notify(format!("matrix: resolving {}: {}", alias, e)))?;
+        room["room_id"]
+            .as_str()
+            .map(|x| x.to_string())
+            .ok_or_else(|| LmkError::Notify(format!("matrix: no room_id for {}", alias)))
+    }
+}
+
+// Returns the m.room.message event of msg followed by link, the link to the match, with a plain
+// body for clients that don't render HTML.
+fn payload(msg: &str, link: &str) -> Value {
+    json!({
+        "msgtype": "m.text",
+        "body": format!("{}\n\n{}", msg, link),
+        "format": "org.matrix.custom.html",
+        "formatted_body": formatting::html(msg, link),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::matchers::{contains, eq, json_decoded, matches, request};
+    use httptest::responders::{json_encoded, status_code};
+    use httptest::{all_of, cycle, Expectation};
+    use std::sync::{Arc, Mutex};
+
+    fn target() -> Target {
+        Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matrix_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        // Every attempt of a message uses the same transaction id.
+        let paths = Arc::new(Mutex::new(vec![]));
+        let recorded = paths.clone();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("PUT"),
+                request::path(matches(
+                    "^/_matrix/client/v3/rooms/!jobs:matrix.org/send/m.room.message/[0-9a-f-]{36}$"
+                )),
+                request::headers(contains(("authorization", "Bearer secret"))),
+                request::body(json_decoded(eq(json!({
+                    "msgtype": "m.text",
+                    "body": "Found match: <Curator>\n\nhttps://museum.org/jobs/1",
+                    "format": "org.matrix.custom.html",
+                    "formatted_body": "<pre>Found match: &lt;Curator&gt;</pre>\n<p><a href=\"https://museum.org/jobs/1\">https://museum.org/jobs/1</a></p>\n",
+                })))),
+                move |x: &httptest::http::Request<httptest::bytes::Bytes>| {
+                    recorded.lock().unwrap().push(x.uri().path().to_string());
+                    true
+                },
+            ])
+            .times(2)
+            .respond_with(cycle![
+                status_code(500),
+                json_encoded(json!({"event_id": "$1"})),
+            ]),
+        );
+        let sender =
+            MatrixSender::with_token(&server.url_str(""), "secret".to_string(), Duration::ZERO)?;
+        sender.send(
+            "!jobs:matrix.org",
+            &target(),
+            "Found match: <Curator>".to_string(),
+            "https://museum.org/jobs/1",
+        )?;
+        let paths = paths.lock().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0], paths[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_sender_alias() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path(
+                "GET",
+                "/_matrix/client/v3/directory/room/%23jobs:matrix.org",
+            ))
+            .respond_with(json_encoded(json!({"room_id": "!jobs:matrix.org"}))),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("PUT"),
+                request::path(matches("^/_matrix/client/v3/rooms/!jobs:matrix.org/send/")),
+            ])
+            .respond_with(json_encoded(json!({"event_id": "$1"}))),
+        );
+        let sender =
+            MatrixSender::with_token(&server.url_str("/"), "secret".to_string(), Duration::ZERO)?;
+        sender.send(
+            "#jobs:matrix.org",
+            &target(),
+            "Found match: Curator".to_string(),
+            &target().uri,
+        )?;
+        assert!(
+            MatrixSender::with_token("matrix.org", "secret".to_string(), Duration::ZERO).is_err()
+        );
+        Ok(())
+    }
+}
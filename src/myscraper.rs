@@ -1,46 +1,436 @@
+use chrono::{SecondsFormat, TimeZone, Utc};
 use itertools::Itertools;
 use opentelemetry::global;
 use opentelemetry::trace::Span;
 use opentelemetry::trace::Tracer;
 use opentelemetry::Context;
 use opentelemetry::KeyValue;
+use regex::Regex;
 use scraper::Html;
 use scraper::Selector;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write as OtherWrite;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::sync::mpsc;
+use std::path::PathBuf;
+use std::sync::{mpsc, RwLock};
 use std::thread;
 use std::time::UNIX_EPOCH;
+use url::Url;
 
-use crate::db::Db;
+use crate::boards::{self, Posting};
+use crate::channel::Channels;
+use crate::crawl;
+use crate::db::{now_secs, Db};
+use crate::diff;
+use crate::error::LmkError;
+pub use crate::fetcher::Response;
+use crate::fetcher::{FetchError, Fetcher, Renderer, Request, ReqwestFetcher};
+use crate::jsonld;
+use crate::pdf;
+use crate::record;
 use crate::scoped_timer::ScopedTimer;
+use crate::session::{self, Cookie, Session};
+use crate::template::{Fields, Template};
+use crate::threshold;
+use crate::warc::WarcWriter;
+
+// TargetKind defines how the content fetched for a target is interpreted.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    // A regular html page, `text` is matched against the text nodes of the page.
+    #[default]
+    Html,
+    // A greenhouse job board, `board` is the greenhouse board token.
+    Greenhouse,
+    // A lever job board, `board` is the lever site name.
+    Lever,
+    // Pages linked from `uri` (or listed in the sitemap at `uri`) whose url matches `follow` are
+    // each checked for `text`, pages are only fetched and reported the first time they're seen.
+    Crawl,
+}
+
+// TargetMode defines what is reported for html targets.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetMode {
+    // New text matching `text` is reported.
+    #[default]
+    Match,
+    // Any change to the text of the `selector` region is reported as a diff.
+    Diff,
+    // A number is extracted from the `selector` region, changes past `above`, `below` or by more
+    // than `change_percent` are reported.
+    Threshold,
+}
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
 pub struct Target {
-    // The uri the scraper should scrape.
+    // The uri the scraper should scrape. Can be left empty for job board targets, in which case
+    // the public api of the board is used.
+    #[serde(default)]
     pub uri: String,
-    // The text to search in the html content of `uri`.
+    // The text to search in the html content of `uri`, diff and threshold mode targets don't
+    // need one.
+    #[serde(default)]
     pub text: String,
     // Description of what the target is, only for humans.
     #[serde(default)]
     pub description: String,
+    // How the content of `uri` is interpreted, defaults to html.
+    #[serde(default)]
+    pub kind: TargetKind,
+    // Board token for greenhouse and lever targets.
+    #[serde(default)]
+    pub board: String,
+    // Css selector of the link to the next page of a paginated listing, e.g., "a.next".
+    #[serde(default)]
+    pub next_page: String,
+    // Uri template of the pages of a paginated listing, "{page}" is replaced by the page number
+    // (starting at 2), e.g., "https://museum.org/jobs?page={page}".
+    #[serde(default)]
+    pub page_template: String,
+    // Maximum number of pages fetched for paginated targets, or of new pages fetched per run for
+    // crawl targets. Defaults to DEFAULT_MAX_PAGES.
+    #[serde(default)]
+    pub max_pages: Option<usize>,
+    // Regex of the urls a crawl target follows, e.g., "museum.org/jobs/\d+".
+    #[serde(default)]
+    pub follow: String,
+    // How many links deep a crawl target follows from `uri`, defaults to 1. Note that nested
+    // sitemaps of a sitemap index count as a level.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    // What is reported for html targets, defaults to matches of `text`.
+    #[serde(default)]
+    pub mode: TargetMode,
+    // Css selector of the region of the page that diff and threshold mode targets watch, defaults
+    // to the body.
+    #[serde(default)]
+    pub selector: String,
+    // Minimum number of changed (removed plus added) lines for diff mode targets to report a
+    // change, smaller changes accumulate until they reach it. Defaults to 1.
+    #[serde(default)]
+    pub min_change: usize,
+    // Regex locating the number of threshold mode targets in the `selector` region, the number is
+    // taken from the first capture group. Defaults to the first number in the region.
+    #[serde(default)]
+    pub regex: String,
+    // Threshold mode targets report when their number rises above `above` or drops below `below`.
+    #[serde(default)]
+    pub above: Option<f64>,
+    #[serde(default)]
+    pub below: Option<f64>,
+    // Threshold mode targets report when their number changes by more than this percentage.
+    #[serde(default)]
+    pub change_percent: Option<f64>,
+    // Regex of the urls of linked PDFs (e.g., job descriptions) whose text is searched for `text`
+    // too, e.g., "museum.org/.*\.pdf$".
+    #[serde(default)]
+    pub pdf_links: String,
+    // PDFs larger than this many bytes are skipped, defaults to DEFAULT_MAX_PDF_BYTES.
+    #[serde(default)]
+    pub max_pdf_bytes: Option<u64>,
+    // Name of the login session (in the sessions of the config) whose cookies are sent when
+    // fetching the target.
+    #[serde(default)]
+    pub session: String,
+    // Whether the target is rendered client side (with javascript), in which case it's fetched
+    // through the renderer, see the --renderer-uri flag.
+    #[serde(default)]
+    pub render: bool,
+    // Names of the notification channels (in the channels of the config) matches are sent to,
+    // defaults to the "default" channel.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    // Priority of push notifications (ntfy, gotify) from 1 (min) to 5 (max), defaults to
+    // DEFAULT_PRIORITY.
+    #[serde(default)]
+    pub priority: Option<u8>,
+    // Tags of push notifications, e.g., "art". ntfy shows the tags that are emoji short codes as
+    // emojis.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Template of the messages sent about the matches of the target, defaults to the
+    // message_template of the config, see the template module.
+    #[serde(default)]
+    pub message_template: Option<Template>,
+}
+
+impl Target {
+    const DEFAULT_MAX_PAGES: usize = 10;
+    const DEFAULT_MAX_PDF_BYTES: u64 = 10 << 20;
+    const DEFAULT_PRIORITY: u8 = 3;
+
+    // Returns the names of the notification channels of the target, e.g., "contemporary,default".
+    pub fn channel_names(&self) -> String {
+        if self.channels.is_empty() {
+            return Channels::DEFAULT.to_string();
+        }
+        self.channels.join(",")
+    }
+
+    // Returns the priority of the push notifications of the target, from 1 (min) to 5 (max).
+    pub fn priority(&self) -> u8 {
+        self.priority.unwrap_or(Self::DEFAULT_PRIORITY)
+    }
+
+    // Returns the uri that should be fetched for this target.
+    pub fn fetch_uri(&self) -> String {
+        if !self.uri.is_empty() {
+            return self.uri.clone();
+        }
+        match self.kind {
+            TargetKind::Html | TargetKind::Crawl => self.uri.clone(),
+            TargetKind::Greenhouse => boards::greenhouse_api_uri(&self.board),
+            TargetKind::Lever => boards::lever_api_uri(&self.board),
+        }
+    }
+
+    // Returns the selector of the region watched by diff and threshold mode targets.
+    fn region_selector(&self) -> Result<Selector, LmkError> {
+        let selector = if self.selector.is_empty() {
+            "body"
+        } else {
+            &self.selector
+        };
+        Selector::parse(selector).map_err(|e| {
+            LmkError::Config(format!(
+                "target {} has invalid selector {:?}: {:?}",
+                self.uri, selector, e
+            ))
+        })
+    }
+
+    // Returns the regex locating the number of threshold mode targets, if any.
+    fn number_regex(&self) -> Result<Option<Regex>, LmkError> {
+        match self.regex.as_str() {
+            "" => Ok(None),
+            regex => Regex::new(regex).map(Some).map_err(|e| {
+                LmkError::Config(format!(
+                    "target {} has invalid regex {:?}: {}",
+                    self.uri, regex, e
+                ))
+            }),
+        }
+    }
+
+    // Returns the regex of the urls a crawl target follows.
+    fn follow_regex(&self) -> Result<Regex, LmkError> {
+        Regex::new(&self.follow).map_err(|e| {
+            LmkError::Config(format!(
+                "target {} has invalid follow regex {:?}: {}",
+                self.uri, self.follow, e
+            ))
+        })
+    }
+
+    // Returns the regex of the urls of the PDFs linked from the target.
+    fn pdf_links_regex(&self) -> Result<Regex, LmkError> {
+        Regex::new(&self.pdf_links).map_err(|e| {
+            LmkError::Config(format!(
+                "target {} has invalid pdf_links regex {:?}: {}",
+                self.uri, self.pdf_links, e
+            ))
+        })
+    }
+
+    // Returns the selector of the link to the next page of a paginated listing.
+    fn next_page_selector(&self) -> Result<Selector, LmkError> {
+        Selector::parse(&self.next_page).map_err(|e| {
+            LmkError::Config(format!(
+                "target {} has invalid next_page selector {:?}: {:?}",
+                self.uri, self.next_page, e
+            ))
+        })
+    }
+
+    // Checks the selectors and regexes of the target, so that a typo is reported when the config
+    // is read rather than on every run.
+    pub fn validate(&self) -> Result<(), LmkError> {
+        // Every text contains the empty text, only diff and threshold targets can go without one.
+        if self.text.is_empty() && self.mode == TargetMode::Match {
+            return Err(LmkError::Config(format!(
+                "target {} has no text to look for",
+                self.uri
+            )));
+        }
+        self.region_selector()?;
+        self.number_regex()?;
+        self.follow_regex()?;
+        self.pdf_links_regex()?;
+        if !self.next_page.is_empty() {
+            self.next_page_selector()?;
+        }
+        Ok(())
+    }
+
+    // Returns the maximum number of pages that should be fetched for this target.
+    fn max_pages(&self) -> usize {
+        if self.kind != TargetKind::Crawl
+            && self.next_page.is_empty()
+            && self.page_template.is_empty()
+        {
+            return 1;
+        }
+        self.max_pages.unwrap_or(Self::DEFAULT_MAX_PAGES)
+    }
+
+    // Returns the uri of page number page_num given the previous page, None if there's no next
+    // page.
+    fn next_page_uri(&self, previous: &Response, page_num: usize) -> Option<String> {
+        if !self.page_template.is_empty() {
+            return Some(self.page_template.replace("{page}", &page_num.to_string()));
+        }
+        let selector = match self.next_page_selector() {
+            Ok(selector) => selector,
+            Err(e) => {
+                log::warn!("{}", e);
+                return None;
+            }
+        };
+        let page = Html::parse_document(&previous.body);
+        let href = page
+            .select(&selector)
+            .find_map(|x| x.value().attr("href"))?;
+        match Url::parse(&previous.url).and_then(|base| base.join(href)) {
+            Ok(next) => Some(next.to_string()),
+            Err(e) => {
+                log::warn!(
+                    "invalid next page link {:?} in {}: {}",
+                    href,
+                    previous.url,
+                    e
+                );
+                None
+            }
+        }
+    }
 }
 
 // Sender sends messages to the given addr.
 // User can provide implementations that email, log or print matches.
 pub trait Sender {
-    fn send(&self, addr: &str, target: &Target, msg: String);
+    // Name of the backend, used in the delivery metrics, e.g., "telegram".
+    fn name(&self) -> String;
+
+    // Sends msg about a match to addr, link is the link to the match, e.g., the url of a new
+    // posting, or the uri of the target.
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError>;
+
+    // Whether the backend sends to the addresses of the notification channels of targets. Local
+    // backends, e.g., print, get every message.
+    fn routed(&self) -> bool {
+        true
+    }
+
+    // Sends msg, returning the (backend name, result) of every backend it was sent through.
+    // Senders that aren't routed to addresses (see CompositeSender) send to the names of the
+    // target's notification channels.
+    fn deliver(
+        &self,
+        target: &Target,
+        msg: String,
+        link: &str,
+    ) -> Vec<(String, Result<(), LmkError>)> {
+        vec![(
+            self.name(),
+            self.send(&target.channel_names(), target, msg, link),
+        )]
+    }
 }
 
 /// Sender implementation that just calls println with arguments.
 pub struct PrintSender {}
 
 impl Sender for PrintSender {
-    fn send(&self, addr: &str, t: &Target, msg: String) {
+    fn name(&self) -> String {
+        "print".to_string()
+    }
+
+    fn send(&self, addr: &str, t: &Target, msg: String, _link: &str) -> Result<(), LmkError> {
         println!("[to {}] Target {}. msg: \n {}", addr, t.uri, msg);
+        Ok(())
+    }
+
+    fn routed(&self) -> bool {
+        false
+    }
+}
+
+// Sender that sends every message through each of its senders, e.g., to print matches and send
+// them on telegram. A failing sender doesn't keep the message from the others, and the senders
+// send concurrently so that a slow one (e.g., one waiting out a rate limit) doesn't hold up the
+// others. Routed senders send to the addresses of the target's notification channels.
+pub struct CompositeSender {
+    senders: Vec<Box<dyn Sender + Send + Sync>>,
+    channels: Channels,
+}
+
+impl CompositeSender {
+    pub fn new(senders: Vec<Box<dyn Sender + Send + Sync>>, channels: Channels) -> Self {
+        CompositeSender { senders, channels }
+    }
+}
+
+impl Sender for CompositeSender {
+    fn name(&self) -> String {
+        self.senders
+            .iter()
+            .map(|x| x.name())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    // Fails if any of the senders failed, addr is unused as the senders are routed.
+    fn send(&self, _addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        self.deliver(target, msg, link)
+            .into_iter()
+            .try_for_each(|(_, result)| result)
+    }
+
+    fn deliver(
+        &self,
+        target: &Target,
+        msg: String,
+        link: &str,
+    ) -> Vec<(String, Result<(), LmkError>)> {
+        thread::scope(|s| {
+            let handles: Vec<_> = self
+                .senders
+                .iter()
+                .map(|sender| {
+                    let msg = msg.clone();
+                    let handle = s.spawn(move || {
+                        if !sender.routed() {
+                            return sender.deliver(target, msg, link);
+                        }
+                        // Not sending the message at all would pass for delivering it.
+                        let addrs = self.channels.addrs(&target.channels, &sender.name());
+                        if addrs.is_empty() {
+                            let e =
+                                format!("no {} address for target {}", sender.name(), target.uri);
+                            return vec![(sender.name(), Err(LmkError::Notify(e)))];
+                        }
+                        addrs
+                            .iter()
+                            .map(|x| (sender.name(), sender.send(x, target, msg.clone(), link)))
+                            .collect()
+                    });
+                    (sender.name(), handle)
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|(name, handle)| {
+                    handle.join().unwrap_or_else(|_| {
+                        vec![(name, Err(LmkError::Notify("sender panicked".to_string())))]
+                    })
+                })
+                .collect()
+        })
     }
 }
 
@@ -88,7 +478,7 @@ impl Metrics {
                         write_buffer(&mut buffer);
                     }
                 }
-                if buffer.len() > 0 {
+                if !buffer.is_empty() {
                     write_buffer(&mut buffer);
                 }
                 log::info!("finished metrics writer thread...");
@@ -116,17 +506,32 @@ impl Metrics {
     // -timestmap is seconds since unix epoch
     fn increment_num_requests(&self, target: &str, status: &str) {
         let _timer = ScopedTimer::new("increment_num_requests".into());
+        self.write(format!("inc_req,{},{}", target, status));
+    }
+
+    // Writes <timestamp>,delivery,<target>,<backend>,<status> to the log file, status is "ok" or
+    // the error the backend failed with.
+    fn record_delivery(&self, target: &str, backend: &str, status: &str) {
+        self.write(format!("delivery,{},{},{}", target, backend, status));
+    }
+
+    // Metrics that aren't written anywhere, e.g., of test runs on saved content.
+    fn disabled() -> Self {
+        Metrics {
+            log_writer: None,
+            writer_thread: None,
+        }
+    }
+
+    fn write(&self, entry: String) {
         let now = std::time::SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        if let Err(e) = self
-            .log_writer
-            .as_ref()
-            .unwrap()
-            .send(format!("{:?},inc_req,{},{}", now, target, status))
-        {
-            log::warn!("failed to write to log sink... {}", e);
+        if let Some(log_writer) = &self.log_writer {
+            if let Err(e) = log_writer.send(format!("{:?},{}", now, entry)) {
+                log::warn!("failed to write to log sink... {}", e);
+            }
         }
     }
 }
@@ -140,7 +545,7 @@ impl Drop for Metrics {
     }
 }
 
-pub struct Scraper<'a, S> {
+pub struct Scraper<'a, S, F = ReqwestFetcher> {
     // The targets to scrape.
     targets: Vec<Target>,
     // Used to send notifications.
@@ -149,32 +554,126 @@ pub struct Scraper<'a, S> {
     metrics: Metrics,
     // Cache of Scraper::target_id(target) -> matching results.
     target_cache: std::cell::RefCell<Db>,
+    fetch_options: FetchOptions<F>,
 }
 
 // ThreadMessage is an enum sent from the threads we spawn to do the requests.
 enum ThreadMessage {
-    Ok(String),
-    Err(String),
+    // The pages fetched for the target, and the PDFs linked from them.
+    Ok(Vec<Response>, Vec<Pdf>),
+    // The (url, response) of the newly discovered pages of a crawl target.
+    Crawled(Vec<(String, Response)>),
+    Err(LmkError),
+}
+
+// Options of how the scraper fetches uris.
+#[derive(Default)]
+pub struct FetchOptions<F = ReqwestFetcher> {
+    // Sends the requests.
+    pub fetcher: F,
+    // If set, every fetch returns this content instead of fetching the uri. Used to run saved
+    // pages of html targets through the scraper, see the --stdin flag.
+    pub stdin: Option<String>,
+    // If set, every fetched response is stored in this directory, see the record module.
+    pub record: Option<PathBuf>,
+    // If set, responses are read from this directory (as written by `record`) instead of being
+    // fetched.
+    pub replay: Option<PathBuf>,
+    // If set, every fetched response is archived in WARC files.
+    pub warc: Option<WarcWriter>,
+    // The rendering service targets with render set are fetched through.
+    pub renderer: Option<Renderer>,
+    // The login sessions targets can use, by name.
+    pub sessions: HashMap<String, Session>,
+    // The cookies of the sessions of the targets by session name, set up by
+    // Scraper::start_sessions before the targets are fetched. Targets only send the cookies of
+    // their own session.
+    pub cookies: RwLock<HashMap<String, Vec<Cookie>>>,
+}
+
+impl<F> FetchOptions<F> {
+    // Returns the options of fetching with fetcher and nothing else set.
+    pub fn new(fetcher: F) -> Self {
+        FetchOptions {
+            fetcher,
+            stdin: None,
+            record: None,
+            replay: None,
+            warc: None,
+            renderer: None,
+            sessions: HashMap::new(),
+            cookies: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+// A match reported by Scraper::notify.
+struct Found<'a> {
+    // The text that matched, the {match} of message templates.
+    m: &'a str,
+    // The match as kept in the match history, e.g., the url of a new page along with m.
+    key: String,
+    // Link to the match, the {link} of message templates.
+    link: &'a str,
+    // The space separated ids of the WARC records of the page (or pages, for diffs and numbers) m
+    // was found in.
+    warc_record_ids: &'a str,
+}
+
+// The text of a PDF linked from a target. The PDFs of a target are cached by url along with their
+// ETag, so that unchanged PDFs aren't downloaded again.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct Pdf {
+    pub url: String,
+    pub etag: Option<String>,
+    pub text: String,
+    // The id of the WARC record the PDF was archived in, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warc_record_id: Option<String>,
 }
 
+#[cfg(test)]
 impl<'a, S> Scraper<'a, S>
 where
     S: Sender,
 {
-    pub fn new(targets: Vec<Target>, sender: &'a S) -> Scraper<'a, S> {
-        let metrics = Metrics::new();
-        let db_path = "./.scraper_target_cache.db";
-        let target_cache = std::cell::RefCell::new(Db::new(&db_path).unwrap());
-        Scraper {
+    fn new_in_memory(targets: Vec<Target>, sender: &'a S) -> Scraper<'a, S> {
+        let fetcher = ReqwestFetcher::new(ReqwestFetcher::DEFAULT_TIMEOUT).unwrap();
+        Self::with_fetcher(targets, sender, fetcher)
+    }
+}
+
+impl<'a, S, F> Scraper<'a, S, F>
+where
+    S: Sender,
+    F: Fetcher,
+{
+    // Creates a scraper of targets whose cache is at db_path (":memory:" for an in memory cache).
+    pub fn new(
+        targets: Vec<Target>,
+        sender: &'a S,
+        db_path: &str,
+        fetch_options: FetchOptions<F>,
+    ) -> Result<Scraper<'a, S, F>, LmkError> {
+        // Runs on stdin or replayed content aren't real scrapes, they're left out of the metrics.
+        let metrics = if fetch_options.stdin.is_some() || fetch_options.replay.is_some() {
+            Metrics::disabled()
+        } else {
+            Metrics::new()
+        };
+        let target_cache = std::cell::RefCell::new(Db::new(db_path)?);
+        Ok(Scraper {
             targets,
             sender,
             metrics,
             target_cache,
-        }
+            fetch_options,
+        })
     }
 
+    // Creates a scraper with an in memory cache whose pages are fetched by fetcher.
     #[cfg(test)]
-    fn new_in_memory(targets: Vec<Target>, sender: &'a S) -> Scraper<'a, S> {
+    fn with_fetcher(targets: Vec<Target>, sender: &'a S, fetcher: F) -> Scraper<'a, S, F> {
         let metrics = Metrics::new_in_memory();
         let target_cache = std::cell::RefCell::new(Db::new_in_memory().unwrap());
         Scraper {
@@ -182,42 +681,270 @@ where
             sender,
             metrics,
             target_cache,
+            fetch_options: FetchOptions::new(fetcher),
         }
     }
 
-    // Returns interesting KeyValues from from an http resonse to add to a span event.
-    fn http_response_trace_events(response: &reqwest::blocking::Response) -> Vec<KeyValue> {
-        let mut result = vec![
-            KeyValue::new(
-                "content_length",
-                response
-                    .content_length()
-                    .unwrap_or(u64::max_value())
-                    .try_into()
-                    .unwrap_or(-1),
-            ),
-            KeyValue::new("status_code", response.status().to_string()),
-            KeyValue::new("final_url", response.url().to_string()),
-            KeyValue::new("http_version", format!("{:?}", response.version())),
-            KeyValue::new(
-                "remote_addr",
-                response
-                    .remote_addr()
-                    .map_or("unknown".to_string(), |addr| addr.to_string()),
-            ),
-        ];
+    // Fetches uri of target, adding the response as an event to span. Returns the failure status
+    // on errors. Targets with render set are fetched through options.renderer.
+    fn fetch(
+        options: &FetchOptions<F>,
+        target: &Target,
+        uri: &str,
+        span: &mut impl Span,
+    ) -> Result<Response, LmkError> {
+        Self::fetch_with(options, uri, span, |span| {
+            let cookies = Self::session_cookies(options, target);
+            if target.render {
+                let Some(renderer) = &options.renderer else {
+                    return Err(LmkError::Config("no renderer configured".to_string()));
+                };
+                let rendered = options
+                    .fetcher
+                    .fetch(&renderer.request(uri, &cookies), span)?;
+                renderer.response(uri, rendered)
+            } else {
+                let mut request = Request::get(uri);
+                if let Some(cookies) = session::cookie_header(&cookies, uri, now_secs()) {
+                    request = request.header("cookie", &cookies);
+                }
+                Ok(options.fetcher.fetch(&request, span)?)
+            }
+        })
+    }
+
+    // Returns the cookies of the session of target.
+    fn session_cookies(options: &FetchOptions<F>, target: &Target) -> Vec<Cookie> {
+        let cookies = options.cookies.read().unwrap();
+        cookies.get(&target.session).cloned().unwrap_or_default()
+    }
+
+    // Returns the response of uri as fetched by fetch, which is archived and recorded according
+    // to options. With --stdin and --replay the saved response is returned instead.
+    fn fetch_with<S2: Span>(
+        options: &FetchOptions<F>,
+        uri: &str,
+        span: &mut S2,
+        fetch: impl FnOnce(&mut S2) -> Result<Response, LmkError>,
+    ) -> Result<Response, LmkError> {
+        if let Some(body) = &options.stdin {
+            return Ok(Response {
+                url: uri.to_string(),
+                status: 200,
+                headers: vec![],
+                body: body.clone(),
+                ..Default::default()
+            });
+        }
+        if let Some(dir) = &options.replay {
+            return record::load(dir, uri).map_err(|e| {
+                log::warn!("no recorded response for {:?} in {:?}: {}", uri, dir, e);
+                LmkError::Fetch(format!("not recorded: {}", e.kind()))
+            });
+        }
+        let mut response = fetch(span)?;
+        if let Some(warc) = &options.warc {
+            match warc.write(uri, &response) {
+                Ok(id) => response.warc_record_id = Some(id),
+                Err(e) => log::warn!("failed to archive response of {:?}: {}", uri, e),
+            }
+        }
+        if let Some(dir) = &options.record {
+            if let Err(e) = record::save(dir, uri, &response) {
+                log::warn!("failed to record response of {:?} in {:?}: {}", uri, dir, e);
+            }
+        }
+        Ok(response)
+    }
+
+    // Fetches the pages of target. For paginated targets the next pages are followed until there
+    // are no more pages or target.max_pages is reached, failing to fetch a next page just ends the
+    // pagination.
+    fn fetch_pages(
+        options: &FetchOptions<F>,
+        target: &Target,
+        span: &mut impl Span,
+    ) -> Result<Vec<Response>, LmkError> {
+        let first = Self::fetch(options, target, &target.fetch_uri(), span)?;
+        let mut visited = HashSet::from([first.url.clone()]);
+        let mut pages = vec![first];
+        while pages.len() < target.max_pages() {
+            let last = pages.last().unwrap();
+            let next = match target.next_page_uri(last, pages.len() + 1) {
+                Some(next) if visited.insert(next.clone()) => next,
+                _ => break,
+            };
+            match Self::fetch(options, target, &next, span) {
+                Ok(page) if (200..300).contains(&page.status) && page.body != last.body => {
+                    pages.push(page)
+                }
+                _ => break,
+            }
+        }
+        span.set_attribute(KeyValue::new("num_pages", pages.len() as i64));
+        Ok(pages)
+    }
+
+    // Fetches the PDFs linked from pages that match target.pdf_links. PDFs in cached whose ETag
+    // didn't change are reused, as are PDFs that fail to fetch. Other failing or too large PDFs
+    // are skipped.
+    fn fetch_pdfs(
+        options: &FetchOptions<F>,
+        target: &Target,
+        pages: &[Response],
+        cached: &HashMap<String, Pdf>,
+        span: &mut impl Span,
+    ) -> Vec<Pdf> {
+        if target.pdf_links.is_empty() {
+            return vec![];
+        }
+        // Saved pages come without their PDFs.
+        if options.stdin.is_some() {
+            return cached.values().cloned().collect();
+        }
+        let pdf_links = match target.pdf_links_regex() {
+            Ok(x) => x,
+            Err(e) => {
+                log::warn!("{}", e);
+                return vec![];
+            }
+        };
+        let max_bytes = target
+            .max_pdf_bytes
+            .unwrap_or(Target::DEFAULT_MAX_PDF_BYTES);
+        let pdfs: Vec<_> = pages
+            .iter()
+            .flat_map(|x| crawl::links(&x.url, &x.body, &pdf_links))
+            .unique()
+            .filter_map(|url| {
+                match Self::fetch_pdf(options, target, &url, cached.get(&url), max_bytes, span) {
+                    Ok(pdf) => Some(pdf),
+                    Err(e) => {
+                        // Keep the last known text so its matches aren't reported again.
+                        log::warn!("failed to fetch pdf {:?}: {}", url, e);
+                        cached.get(&url).cloned()
+                    }
+                }
+            })
+            .collect();
+        span.set_attribute(KeyValue::new("num_pdfs", pdfs.len() as i64));
+        pdfs
+    }
 
-        for (name, v) in response.headers() {
-            if let Ok(v) = v.to_str() {
-                result.push(KeyValue::new(format!("HEADER[{}]", name), v.to_string()));
+    // Fetches the PDF at url linked from target and extracts its text, unless it has the ETag of
+    // cached.
+    fn fetch_pdf(
+        options: &FetchOptions<F>,
+        target: &Target,
+        url: &str,
+        cached: Option<&Pdf>,
+        max_bytes: u64,
+        span: &mut impl Span,
+    ) -> Result<Pdf, LmkError> {
+        let response = Self::fetch_with(options, url, span, |span| {
+            let mut request = Request {
+                binary: true,
+                max_bytes: Some(max_bytes),
+                ..Request::get(url)
+            };
+            let cookies = Self::session_cookies(options, target);
+            if let Some(cookies) = session::cookie_header(&cookies, url, now_secs()) {
+                request = request.header("cookie", &cookies);
+            }
+            if let Some(etag) = cached.and_then(|x| x.etag.as_ref()) {
+                request = request.header("if-none-match", etag);
             }
+            options.fetcher.fetch(&request, span).map_err(|e| match e {
+                FetchError::TooLarge => {
+                    LmkError::Fetch(format!("pdf is larger than {} bytes", max_bytes))
+                }
+                e => e.into(),
+            })
+        })?;
+        if let (304, Some(cached)) = (response.status, cached) {
+            return Ok(cached.clone());
+        }
+        if !(200..300).contains(&response.status) {
+            return Err(LmkError::Fetch(response.status.to_string()));
         }
+        let etag = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("etag"))
+            .map(|(_, value)| value.clone());
+        Ok(Pdf {
+            url: url.to_string(),
+            etag,
+            text: pdf::extract_text(&response.data)?,
+            warc_record_id: response.warc_record_id,
+        })
+    }
 
-        result
+    // Discovers the pages of a crawl target, returning the (url, response) of the pages that aren't in
+    // seen. Links are followed breadth first up to target.max_depth, at most target.max_pages new
+    // pages are fetched, the rest are left for the next run. Seen pages are still fetched for their
+    // links, unless they're at max_depth.
+    fn crawl(
+        options: &FetchOptions<F>,
+        target: &Target,
+        seen: &HashSet<String>,
+        span: &mut impl Span,
+    ) -> Result<Vec<(String, Response)>, LmkError> {
+        let follow = target.follow_regex()?;
+        let start = Self::fetch(options, target, &target.fetch_uri(), span)?;
+        let mut visited = HashSet::from([start.url.clone()]);
+        let mut new_pages = vec![];
+        let mut frontier = vec![start];
+        let max_depth = target.max_depth.unwrap_or(1);
+        for depth in 1..=max_depth {
+            let mut next_frontier = vec![];
+            for page in &frontier {
+                let (sitemaps, links) = match crawl::parse_sitemap(&page.body) {
+                    Some(sitemap) => (
+                        sitemap.sitemaps,
+                        sitemap
+                            .pages
+                            .into_iter()
+                            .filter(|x| follow.is_match(x))
+                            .collect(),
+                    ),
+                    None => (vec![], crawl::links(&page.url, &page.body, &follow)),
+                };
+                for uri in sitemaps {
+                    if visited.insert(uri.clone()) {
+                        if let Ok(sitemap) = Self::fetch(options, target, &uri, span) {
+                            next_frontier.push(sitemap);
+                        }
+                    }
+                }
+                for uri in links {
+                    let is_seen = seen.contains(&uri);
+                    if (is_seen && depth == max_depth) || !visited.insert(uri.clone()) {
+                        continue;
+                    }
+                    if new_pages.len() >= target.max_pages() {
+                        break;
+                    }
+                    // Pages that fail to fetch aren't seen, they're retried on the next run.
+                    match Self::fetch(options, target, &uri, span) {
+                        Ok(page) if (200..300).contains(&page.status) => {
+                            if !is_seen {
+                                new_pages.push((uri, page.clone()));
+                            }
+                            next_frontier.push(page);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            frontier = next_frontier;
+        }
+        span.set_attribute(KeyValue::new("num_new_pages", new_pages.len() as i64));
+        Ok(new_pages)
     }
 
     // scrape runs a single scraping iteration, reporting any matches on targets to sender.
-    pub fn scrape(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn scrape(&self) -> Result<(), LmkError> {
         let tracer = global::tracer("scraper");
         let _child_span = tracer.start("scraper.scrape");
         let _scrape_timer = ScopedTimer::new("scrape timer".into());
@@ -230,51 +957,40 @@ where
         // would need to be 'static'.
         // - A threadpool would be better here, a thread per target could be costly. so a future
         // improvement would be to do this in a thread pool or via async things.
+        // Crawl targets only report the pages they haven't seen before.
+        let seen: Vec<_> = self
+            .targets
+            .iter()
+            .map(|t| match t.kind {
+                TargetKind::Crawl => self.target_cache.borrow().seen_urls(&Self::target_id(t)),
+                _ => HashSet::new(),
+            })
+            .collect();
+        self.start_sessions();
+        // PDFs are only downloaded again when they changed.
+        let pdfs: Vec<_> = self.targets.iter().map(|t| self.cached_pdfs(t)).collect();
+        let options = &self.fetch_options;
         thread::scope(|s| {
             let mut handles = vec![];
-            for t in &self.targets {
+            for ((t, seen), pdfs) in self.targets.iter().zip(&seen).zip(&pdfs) {
                 let sender = sender.clone();
                 let current_context = Context::current();
                 handles.push(s.spawn(move || {
                     let tracer = global::tracer("scraper");
+                    let uri = t.fetch_uri();
                     let mut child_span = tracer
-                        .start_with_context(format!("scrape_thread: {}", t.uri), &current_context);
-                    child_span.set_attribute(KeyValue::new("target", t.uri.clone()));
-                    let _timer = ScopedTimer::new(format!("scrape for {}", t.uri));
-                    match reqwest::blocking::get(&t.uri) {
-                        Ok(x) => {
-                            child_span
-                                .add_event("http-response", Self::http_response_trace_events(&x));
-                            let contents = x.text();
-                            if let Err(e) = contents {
-                                child_span.add_event(
-                                    "failed to convert to text",
-                                    vec![
-                                        KeyValue::new("err text", e.to_string()),
-                                        KeyValue::new("uri", t.uri.clone()),
-                                    ],
-                                );
-                                let _ = sender.send((t, ThreadMessage::Err(e.to_string())));
-                                return;
-                            }
-                            let contents = contents.unwrap();
-                            let _ = sender.send((t, ThreadMessage::Ok(contents)));
-                        }
-                        Err(e) => {
-                            let status =
-                                e.status().map_or("unknown".to_string(), |s| s.to_string());
-                            child_span.add_event(
-                                "http-response",
-                                vec![
-                                    KeyValue::new("err text", e.to_string()),
-                                    KeyValue::new("uri", t.uri.clone()),
-                                    KeyValue::new("status", status.clone()),
-                                ],
-                            );
-                            let _ = sender.send((t, ThreadMessage::Err(status)));
-                            log::warn!("failed to scrape {:?}, err: {:?}", t.uri, e);
-                        }
+                        .start_with_context(format!("scrape_thread: {}", uri), &current_context);
+                    child_span.set_attribute(KeyValue::new("target", uri.clone()));
+                    let _timer = ScopedTimer::new(format!("scrape for {}", uri));
+                    let message = if t.kind == TargetKind::Crawl {
+                        Self::crawl(options, t, seen, &mut child_span).map(ThreadMessage::Crawled)
+                    } else {
+                        Self::fetch_pages(options, t, &mut child_span).map(|pages| {
+                            let pdfs = Self::fetch_pdfs(options, t, &pages, pdfs, &mut child_span);
+                            ThreadMessage::Ok(pages, pdfs)
+                        })
                     };
+                    let _ = sender.send((t, message.unwrap_or_else(ThreadMessage::Err)));
                 }));
             }
             // We need to drop the sender before waiting on the receiver because after
@@ -283,18 +999,80 @@ where
             // I imagine there's a more idomatic way to do this.
             drop(sender);
             for (t, resp) in receiver {
+                let uri = t.fetch_uri();
                 match resp {
-                    ThreadMessage::Ok(resp) => {
-                        let page = {
-                            let _timer = ScopedTimer::new(format!("parse_docucment({})", t.uri));
+                    ThreadMessage::Ok(pages, pdfs) => match t.kind {
+                        TargetKind::Html | TargetKind::Crawl => {
+                            let warc_record_ids = Self::warc_record_ids(&pages);
+                            let page_warc_record_ids: Vec<_> = pages
+                                .iter()
+                                .map(|x| x.warc_record_id.clone().unwrap_or_default())
+                                .collect();
+                            let pages: Vec<_> = {
+                                let _timer = ScopedTimer::new(format!("parse_docucment({})", uri));
 
-                            Html::parse_document(&resp)
-                        };
-                        self.handle_page_content(page, t)?;
-                        self.metrics.increment_num_requests(&t.uri, "OK");
+                                pages
+                                    .iter()
+                                    .map(|x| Html::parse_document(&x.body))
+                                    .collect()
+                            };
+                            match t.mode {
+                                TargetMode::Match => {
+                                    self.handle_page_content(pages, t, &page_warc_record_ids)?;
+                                    if !t.pdf_links.is_empty() {
+                                        self.handle_pdfs(pdfs, t)?;
+                                    }
+                                }
+                                TargetMode::Diff => {
+                                    self.handle_page_diff(pages, t, &warc_record_ids)?
+                                }
+                                TargetMode::Threshold => {
+                                    self.handle_page_number(pages, t, &warc_record_ids)?
+                                }
+                            }
+                            self.metrics.increment_num_requests(&uri, "OK");
+                        }
+                        TargetKind::Greenhouse | TargetKind::Lever => {
+                            let parse = if t.kind == TargetKind::Greenhouse {
+                                boards::parse_greenhouse
+                            } else {
+                                boards::parse_lever
+                            };
+                            // Each posting is paired with the WARC record id of its page.
+                            let postings: Result<Vec<_>, _> = pages
+                                .iter()
+                                .map(|x| {
+                                    let id = x.warc_record_id.clone().unwrap_or_default();
+                                    parse(&x.body)
+                                        .map(|ps| ps.into_iter().map(move |p| (p, id.clone())))
+                                })
+                                .flatten_ok()
+                                .collect();
+                            match postings {
+                                Ok(postings) => {
+                                    let (postings, warc_record_ids): (Vec<_>, Vec<_>) =
+                                        postings.into_iter().unzip();
+                                    self.handle_postings(postings, t, &warc_record_ids)?;
+                                    self.metrics.increment_num_requests(&uri, "OK");
+                                }
+                                Err(e) => {
+                                    log::warn!("failed to parse postings of {:?}: {}", t, e);
+                                    self.metrics.increment_num_requests(&uri, "parse_error");
+                                }
+                            }
+                        }
+                    },
+                    ThreadMessage::Crawled(pages) => {
+                        self.handle_crawled_pages(pages, t)?;
+                        self.metrics.increment_num_requests(&uri, "OK");
                     }
                     ThreadMessage::Err(e) => {
-                        self.metrics.increment_num_requests(&t.uri, &e);
+                        log::warn!("failed to scrape {}: {}", uri, e);
+                        let status = match e {
+                            LmkError::Fetch(status) => status,
+                            e => e.to_string(),
+                        };
+                        self.metrics.increment_num_requests(&uri, &status);
                     }
                 };
             }
@@ -306,17 +1084,82 @@ where
         })
     }
 
+    // Returns the space separated ids of the WARC records the pages were archived in.
+    fn warc_record_ids(pages: &[Response]) -> String {
+        pages
+            .iter()
+            .filter_map(|x| x.warc_record_id.as_deref())
+            .join(" ")
+    }
+
+    // Sends msg about the match found of target, recording the match in the match history and
+    // the delivery status of every backend in the metrics. If the target has a message template,
+    // the message is rendered from it with msg as its {message}. Returns whether any backend
+    // delivered msg (or there was no backend to deliver it), matches that weren't delivered are
+    // left out of the target cache so that they're reported again by the next run.
+    fn notify(&self, target: &Target, msg: String, found: Found) -> bool {
+        let cache_id = Self::target_id(target);
+        if let Err(e) =
+            self.target_cache
+                .borrow_mut()
+                .add_match(&cache_id, &found.key, found.warc_record_ids)
+        {
+            log::warn!("failed to write match history: {}", e);
+        }
+        let msg = match &target.message_template {
+            Some(template) => {
+                let first_seen = self
+                    .target_cache
+                    .borrow()
+                    .first_seen(&cache_id, &found.key)
+                    .unwrap_or_else(now_secs);
+                let first_seen = Utc
+                    .timestamp_opt(first_seen as i64, 0)
+                    .single()
+                    .unwrap_or_else(Utc::now)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true);
+                template.render(&Fields {
+                    description: &target.description,
+                    uri: &target.uri,
+                    text: &target.text,
+                    m: found.m,
+                    link: found.link,
+                    first_seen: &first_seen,
+                    message: &msg,
+                })
+            }
+            None => msg,
+        };
+        let deliveries = self.sender.deliver(target, msg, found.link);
+        let delivered = deliveries.is_empty() || deliveries.iter().any(|(_, x)| x.is_ok());
+        for (backend, result) in deliveries {
+            let status = match result {
+                Ok(()) => "ok".to_string(),
+                Err(e) => {
+                    log::warn!("failed to notify {} for {}: {}", backend, target.uri, e);
+                    // Keeps the csv columns intact.
+                    e.to_string().replace([',', '\n'], " ")
+                }
+            };
+            self.metrics.record_delivery(&target.uri, &backend, &status);
+        }
+        delivered
+    }
+
     fn target_id(target: &Target) -> String {
-        std::format!("{}:{}", target.uri, target.text)
+        std::format!("{}:{}", target.fetch_uri(), target.text)
     }
 
     // Checks content for any matches. For each encountered match a notification event is generated.
     // Note that if content has not changed since last handling, no notifcations are generated.
+    // pages holds all the pages of a (possibly paginated) target, their matches are merged.
+    // warc_record_ids[i] is the id of the WARC record pages[i] was archived in, if it was.
     fn handle_page_content(
         &self,
-        page: Html,
+        pages: Vec<Html>,
         target: &Target,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        warc_record_ids: &[String],
+    ) -> Result<(), LmkError> {
         // Create a child span for handling this page's content.
         let tracer = global::tracer("scraper");
         let mut child_span = tracer.start(format!("handle_page_content({})", target.uri));
@@ -341,38 +1184,82 @@ where
         let mut cache_value = String::new();
         // The number of matching items on this page -- added to the span.
         let mut num_new_matches = 0;
-        // Grab all of the content in the html page.
+        // Structured JobPostings embedded in the page are matched by their title and reported with
+        // all of their fields.
+        let page_warc_record_ids = |i: usize| warc_record_ids.get(i).map_or("", String::as_str);
+        let postings: Vec<_> = pages
+            .iter()
+            .enumerate()
+            .flat_map(|(i, page)| {
+                jsonld::extract_job_postings(page)
+                    .into_iter()
+                    .map(move |p| (p, page_warc_record_ids(i)))
+            })
+            .filter(|(p, _)| p.title.contains(&target.text))
+            .unique_by(|(p, _)| p.id.clone())
+            .collect();
+        child_span.set_attribute(KeyValue::new("num_job_postings", postings.len() as i64));
+        let posting_titles: HashSet<_> = postings.iter().map(|(p, _)| p.title.as_str()).collect();
+        let posting_sources: HashSet<_> = pages.iter().flat_map(jsonld::json_ld_sources).collect();
+        // Grab all of the content in the html pages, along with the WARC record of the page.
         let selector = Selector::parse("*").unwrap();
-        let content = page.select(&selector).flat_map(|x| x.text());
+        let content = pages.iter().enumerate().flat_map(|(i, page)| {
+            page.select(&selector)
+                .flat_map(|x| x.text())
+                .map(move |x| (x, page_warc_record_ids(i)))
+        });
         {
             let _timer = ScopedTimer::new(format!("lookup and compare for {}", target.uri));
             // Look up old content and compare
             content
-                .filter_map(|x| {
+                .filter_map(|(x, warc_record_ids)| {
                     // Get the elements that match `target.text`
                     if x.contains(&target.text) {
-                        Some(x)
+                        Some((x, warc_record_ids))
                     } else {
                         None
                     }
                 })
-                // Dedup them
-                .unique()
-                .map(|x| {
+                // Text that belongs to a posting is reported along with that posting.
+                .filter(|(x, _)| !posting_titles.contains(x.trim()) && !posting_sources.contains(x))
+                // Dedup them, a match is linked to the first page it's on.
+                .unique_by(|(x, _)| *x)
+                // Old matches and the new ones that were delivered are cached, undelivered ones are
+                // reported again by the next run.
+                .filter(|(x, warc_record_ids)| {
+                    old_matches.contains(x) || {
+                        num_new_matches += 1;
+                        let found = Found {
+                            m: x,
+                            key: x.to_string(),
+                            link: &target.uri,
+                            warc_record_ids,
+                        };
+                        self.notify(target, format!("Found match: {}", x), found)
+                    }
+                })
+                .for_each(|(x, _)| {
                     // Write the matches into target_caches
                     // writing into a string can't fail.
                     writeln!(cache_value, "{}", x).unwrap();
-                    x
-                })
-                .filter(|x| !old_matches.contains(x))
-                .for_each(|x| {
-                    num_new_matches += 1;
-                    self.sender.send(
-                        "everyone@everyone.com",
-                        &target,
-                        format!("Found match: {}", x),
-                    )
                 });
+            for (posting, warc_record_ids) in &postings {
+                let key = format!("posting:{}", posting.id);
+                let cached = old_matches.contains(key.as_str()) || {
+                    num_new_matches += 1;
+                    let summary = posting.summary();
+                    let found = Found {
+                        m: &summary,
+                        key: summary.clone(),
+                        link: &posting.url,
+                        warc_record_ids,
+                    };
+                    self.notify(target, format!("Found posting: {}", summary), found)
+                };
+                if cached {
+                    writeln!(cache_value, "{}", key).unwrap();
+                }
+            }
         }
         child_span.set_attribute(KeyValue::new("num_new_matches", num_new_matches));
         // TODO(bilal): Write the freshness date as well.
@@ -384,33 +1271,613 @@ where
         }
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use httptest::cycle;
-    use httptest::{matchers::request, responders::status_code, Expectation};
-    use std::cell::RefCell;
+    // Like handle_page_content but for structured job board postings. Postings are matched by
+    // their title and cached by their id, so a posting is only reported once even if it is edited.
+    // warc_record_ids[i] is the id of the WARC record of the page postings[i] was fetched from.
+    fn handle_postings(
+        &self,
+        postings: Vec<Posting>,
+        target: &Target,
+        warc_record_ids: &[String],
+    ) -> Result<(), LmkError> {
+        let tracer = global::tracer("scraper");
+        let mut child_span = tracer.start(format!("handle_postings({})", target.fetch_uri()));
+        let _timer = ScopedTimer::new(format!("handle_postings({})", target.fetch_uri()));
 
-    use super::*;
+        let cache_id = Self::target_id(target);
+        child_span.set_attribute(KeyValue::new("target_cache_id", cache_id.clone()));
+        let old_contents = self
+            .target_cache
+            .borrow()
+            .get(&cache_id)
+            .unwrap_or_default();
+        let old_ids: HashSet<_> = old_contents.lines().collect();
 
-    struct FakeSender {
-        // messages sent to this fake sender
-        msgs: RefCell<Vec<String>>,
-    }
-    impl FakeSender {
-        fn new() -> Self {
-            FakeSender {
-                msgs: RefCell::new(vec![]),
-            }
+        let mut cache_value = String::new();
+        let mut num_new_matches = 0;
+        for (posting, warc_record_ids) in postings
+            .iter()
+            .zip(warc_record_ids)
+            .filter(|(p, _)| p.title.contains(&target.text))
+            .unique_by(|(p, _)| &p.id)
+        {
+            let cached = old_ids.contains(posting.id.as_str()) || {
+                num_new_matches += 1;
+                let summary = posting.summary();
+                let found = Found {
+                    m: &summary,
+                    key: summary.clone(),
+                    link: &posting.url,
+                    warc_record_ids,
+                };
+                self.notify(target, format!("Found posting: {}", summary), found)
+            };
+            if cached {
+                writeln!(cache_value, "{}", posting.id).unwrap();
+            }
+        }
+        child_span.set_attribute(KeyValue::new("num_postings", postings.len() as i64));
+        child_span.set_attribute(KeyValue::new("num_new_matches", num_new_matches));
+        if let Err(e) = self.target_cache.borrow_mut().put(&cache_id, &cache_value) {
+            child_span.set_attribute(KeyValue::new("cache_write", "failed"));
+            log::warn!("failed to write into target_cache: {}", e);
+        } else {
+            child_span.set_attribute(KeyValue::new("cache_write", "succeeded"));
+        }
+        Ok(())
+    }
+
+    // Reports the newly discovered pages of a crawl target that contain target.text, and records
+    // all of them as seen so they aren't fetched again.
+    fn handle_crawled_pages(
+        &self,
+        pages: Vec<(String, Response)>,
+        target: &Target,
+    ) -> Result<(), LmkError> {
+        let tracer = global::tracer("scraper");
+        let mut child_span = tracer.start(format!("handle_crawled_pages({})", target.uri));
+        let _timer = ScopedTimer::new(format!("handle_crawled_pages({})", target.uri));
+
+        let cache_id = Self::target_id(target);
+        let mut num_new_matches = 0;
+        for (url, response) in &pages {
+            let page = Html::parse_document(&response.body);
+            if let Some(x) = page
+                .root_element()
+                .text()
+                .find(|x| x.contains(&target.text) && !x.trim().is_empty())
+            {
+                num_new_matches += 1;
+                let found = Found {
+                    m: x.trim(),
+                    key: format!("{} ({})", url, x.trim()),
+                    link: url,
+                    warc_record_ids: &Self::warc_record_ids(std::slice::from_ref(response)),
+                };
+                let delivered = self.notify(
+                    target,
+                    format!("Found new page: {} ({})", url, x.trim()),
+                    found,
+                );
+                // The page stays new until it's reported.
+                if !delivered {
+                    continue;
+                }
+            }
+            if let Err(e) = self.target_cache.borrow_mut().add_seen_url(&cache_id, url) {
+                log::warn!("failed to write into target_cache: {}", e);
+            }
+        }
+        child_span.set_attribute(KeyValue::new("num_new_pages", pages.len() as i64));
+        child_span.set_attribute(KeyValue::new("num_new_matches", num_new_matches));
+        Ok(())
+    }
+
+    // Sets up the cookies of the login sessions used by the targets. Cookies are reused from
+    // the target cache until they expire, sessions without cookies log in again. Targets of
+    // sessions that fail to log in are fetched without their cookies.
+    fn start_sessions(&self) {
+        let now = now_secs();
+        let mut cookies = HashMap::new();
+        for name in self.targets.iter().map(|t| &t.session).unique() {
+            if name.is_empty() {
+                continue;
+            }
+            let Some(session) = self.fetch_options.sessions.get(name) else {
+                log::warn!("unknown session {:?}", name);
+                continue;
+            };
+            let key = format!("session:{}", name);
+            let cached = self.target_cache.borrow().get(&key);
+            let mut jar: Vec<Cookie> = cached
+                .and_then(|x| serde_json::from_str(&x).ok())
+                .unwrap_or_default();
+            jar.retain(|x| !x.is_expired(now));
+            if jar.is_empty() {
+                log::info!("logging in to session {:?}", name);
+                let tracer = global::tracer("scraper");
+                let mut span = tracer.start(format!("login({})", name));
+                jar = match session.login(&self.fetch_options.fetcher, now, &mut span) {
+                    Ok(jar) => jar,
+                    Err(e) => {
+                        log::warn!("failed to log in to session {:?}: {}", name, e);
+                        continue;
+                    }
+                };
+                let value = serde_json::to_string(&jar).unwrap();
+                if let Err(e) = self.target_cache.borrow_mut().put(&key, &value) {
+                    log::warn!("failed to write into target_cache: {}", e);
+                }
+            }
+            cookies.insert(name.clone(), jar);
+        }
+        *self.fetch_options.cookies.write().unwrap() = cookies;
+    }
+
+    // Returns the PDFs of target cached by handle_pdfs, by url.
+    fn cached_pdfs(&self, target: &Target) -> HashMap<String, Pdf> {
+        if target.pdf_links.is_empty() {
+            return HashMap::new();
+        }
+        let key = format!("pdfs:{}", Self::target_id(target));
+        let cached = self.target_cache.borrow().get(&key);
+        match cached.map(|x| serde_json::from_str::<Vec<Pdf>>(&x)) {
+            Some(Ok(pdfs)) => pdfs.into_iter().map(|x| (x.url.clone(), x)).collect(),
+            Some(Err(e)) => {
+                log::warn!("invalid pdf cache of {}: {}", key, e);
+                HashMap::new()
+            }
+            None => HashMap::new(),
+        }
+    }
+
+    // Reports the new lines of the PDFs linked from a target that contain target.text. The text
+    // of the PDFs is cached along with their ETags.
+    fn handle_pdfs(&self, pdfs: Vec<Pdf>, target: &Target) -> Result<(), LmkError> {
+        let tracer = global::tracer("scraper");
+        let mut child_span = tracer.start(format!("handle_pdfs({})", target.uri));
+        let _timer = ScopedTimer::new(format!("handle_pdfs({})", target.uri));
+
+        let cache_id = format!("pdf-matches:{}", Self::target_id(target));
+        child_span.set_attribute(KeyValue::new("target_cache_id", cache_id.clone()));
+        let old_contents = self
+            .target_cache
+            .borrow()
+            .get(&cache_id)
+            .unwrap_or_default();
+        let old_matches: HashSet<_> = old_contents.lines().collect();
+
+        let mut cache_value = String::new();
+        let mut num_new_matches = 0;
+        for pdf in &pdfs {
+            for line in pdf
+                .text
+                .lines()
+                .filter(|x| x.contains(&target.text))
+                .unique()
+            {
+                let key = format!("{} {}", pdf.url, line);
+                let cached = old_matches.contains(key.as_str()) || {
+                    num_new_matches += 1;
+                    let found = Found {
+                        m: line,
+                        key: format!("{} ({})", line, pdf.url),
+                        link: &pdf.url,
+                        warc_record_ids: pdf.warc_record_id.as_deref().unwrap_or_default(),
+                    };
+                    self.notify(
+                        target,
+                        format!("Found match in {}: {}", pdf.url, line),
+                        found,
+                    )
+                };
+                if cached {
+                    writeln!(cache_value, "{}", key).unwrap();
+                }
+            }
+        }
+        child_span.set_attribute(KeyValue::new("num_pdfs", pdfs.len() as i64));
+        child_span.set_attribute(KeyValue::new("num_new_matches", num_new_matches));
+        let pdf_cache =
+            serde_json::to_string(&pdfs).map_err(|e| LmkError::Storage(e.to_string()))?;
+        let mut target_cache = self.target_cache.borrow_mut();
+        if let Err(e) = target_cache.put(&cache_id, &cache_value).and_then(|_| {
+            target_cache.put(&format!("pdfs:{}", Self::target_id(target)), &pdf_cache)
+        }) {
+            child_span.set_attribute(KeyValue::new("cache_write", "failed"));
+            log::warn!("failed to write into target_cache: {}", e);
+        } else {
+            child_span.set_attribute(KeyValue::new("cache_write", "succeeded"));
+        }
+        Ok(())
+    }
+
+    // Reports changes to the text of the target.selector region of the pages as a diff against
+    // the text that was last reported. The first run only records the text.
+    fn handle_page_diff(
+        &self,
+        pages: Vec<Html>,
+        target: &Target,
+        warc_record_ids: &str,
+    ) -> Result<(), LmkError> {
+        let tracer = global::tracer("scraper");
+        let mut child_span = tracer.start(format!("handle_page_diff({})", target.uri));
+        let _timer = ScopedTimer::new(format!("handle_page_diff({})", target.uri));
+
+        let selector = target.region_selector()?;
+        let text = pages
+            .iter()
+            .map(|page| diff::normalized_text(page, &selector))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let cache_id = format!("diff:{}", Self::target_id(target));
+        child_span.set_attribute(KeyValue::new("target_cache_id", cache_id.clone()));
+        let old_text = self.target_cache.borrow().get(&cache_id);
+        let old_text = match old_text {
+            Some(old_text) => old_text,
+            None => {
+                log::info!("recording initial content of {}", target.uri);
+                self.target_cache.borrow_mut().put(&cache_id, &text)?;
+                return Ok(());
+            }
+        };
+        let change = diff::line_diff(&old_text, &text);
+        child_span.set_attribute(KeyValue::new("changed_lines", change.changed_lines as i64));
+        if change.changed_lines == 0 || change.changed_lines < target.min_change {
+            return Ok(());
+        }
+        let found = Found {
+            m: &change.unified,
+            key: change.unified.clone(),
+            link: &target.uri,
+            warc_record_ids,
+        };
+        let delivered = self.notify(
+            target,
+            format!(
+                "Content changed ({} lines):\n{}",
+                change.changed_lines, change.unified
+            ),
+            found,
+        );
+        // The change is reported again by the next run.
+        if !delivered {
+            return Ok(());
+        }
+        if let Err(e) = self.target_cache.borrow_mut().put(&cache_id, &text) {
+            child_span.set_attribute(KeyValue::new("cache_write", "failed"));
+            log::warn!("failed to write into target_cache: {}", e);
+        } else {
+            child_span.set_attribute(KeyValue::new("cache_write", "succeeded"));
+        }
+        Ok(())
+    }
+
+    // Extracts the number of a threshold mode target from its pages, reports threshold crossings
+    // and large changes compared to the previous value, and records it in the history once the
+    // reports are delivered.
+    fn handle_page_number(
+        &self,
+        pages: Vec<Html>,
+        target: &Target,
+        warc_record_ids: &str,
+    ) -> Result<(), LmkError> {
+        let tracer = global::tracer("scraper");
+        let mut child_span = tracer.start(format!("handle_page_number({})", target.uri));
+        let _timer = ScopedTimer::new(format!("handle_page_number({})", target.uri));
+
+        let selector = target.region_selector()?;
+        let pattern = target.number_regex()?;
+        let text = pages
+            .iter()
+            .map(|page| diff::normalized_text(page, &selector))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let current = match threshold::extract_number(&text, pattern.as_ref()) {
+            Some(current) => current,
+            None => {
+                child_span.set_attribute(KeyValue::new("number", "not found"));
+                log::warn!("no number found for {:?} in {:?}", target, text);
+                return Ok(());
+            }
+        };
+        child_span.set_attribute(KeyValue::new("number", current));
+
+        let cache_id = format!("threshold:{}", Self::target_id(target));
+        let previous = self.target_cache.borrow().last_history(&cache_id);
+        let current_text = current.to_string();
+        let mut delivered = true;
+        for alert in threshold::alerts(target, previous, current) {
+            let found = Found {
+                m: &current_text,
+                key: current_text.clone(),
+                link: &target.uri,
+                warc_record_ids,
+            };
+            delivered &= self.notify(target, alert, found);
+        }
+        // The value is compared with the previous one again by the next run, which reports the
+        // alerts again.
+        if !delivered {
+            return Ok(());
+        }
+        if let Err(e) = self
+            .target_cache
+            .borrow_mut()
+            .add_history(&cache_id, current)
+        {
+            child_span.set_attribute(KeyValue::new("cache_write", "failed"));
+            log::warn!("failed to write into target_cache: {}", e);
+        } else {
+            child_span.set_attribute(KeyValue::new("cache_write", "succeeded"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use httptest::matchers::{contains, eq, json_decoded, key, not, url_decoded};
+    use httptest::{all_of, cycle};
+    use httptest::{matchers::request, responders::status_code, Expectation};
+    use std::cell::RefCell;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::fetcher::FakeFetcher;
+
+    fn reqwest_options() -> FetchOptions {
+        FetchOptions::new(ReqwestFetcher::new(ReqwestFetcher::DEFAULT_TIMEOUT).unwrap())
+    }
+
+    // Returns the file uri of the file name in testdata.
+    fn testdata_uri(name: &str) -> String {
+        let path = std::env::current_dir().unwrap().join("testdata").join(name);
+        Url::from_file_path(path).unwrap().to_string()
+    }
+
+    struct FakeSender {
+        // messages sent to this fake sender
+        msgs: RefCell<Vec<String>>,
+        // the links of the messages
+        links: RefCell<Vec<String>>,
+    }
+    impl FakeSender {
+        fn new() -> Self {
+            FakeSender {
+                msgs: RefCell::new(vec![]),
+                links: RefCell::new(vec![]),
+            }
         }
     }
     impl Sender for FakeSender {
-        fn send(&self, addr: &str, t: &Target, msg: String) {
+        fn name(&self) -> String {
+            "fake".to_string()
+        }
+
+        fn send(&self, addr: &str, t: &Target, msg: String, link: &str) -> Result<(), LmkError> {
             self.msgs
                 .borrow_mut()
                 .push(format!("[to {}] Target {}. msg: \n {}", addr, t.uri, msg));
+            self.links.borrow_mut().push(link.to_string());
+            Ok(())
+        }
+    }
+    impl Sender for Arc<Mutex<FakeSender>> {
+        fn name(&self) -> String {
+            self.lock().unwrap().name()
+        }
+
+        fn send(&self, addr: &str, t: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+            self.lock().unwrap().send(addr, t, msg, link)
+        }
+    }
+
+    // Sender that fails every message.
+    struct FailingSender {}
+    impl Sender for FailingSender {
+        fn name(&self) -> String {
+            "failing".to_string()
+        }
+
+        fn send(
+            &self,
+            _addr: &str,
+            _t: &Target,
+            _msg: String,
+            _link: &str,
+        ) -> Result<(), LmkError> {
+            Err(LmkError::Notify("unreachable".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_composite_sender() {
+        let first = Arc::new(Mutex::new(FakeSender::new()));
+        let second = Arc::new(Mutex::new(FakeSender::new()));
+        let mut channels = Channels::default();
+        channels.set_default_addr("fake", "everyone@everyone.com".to_string());
+        channels.set_default_addr("failing", "everyone@everyone.com".to_string());
+        let sender = CompositeSender::new(
+            vec![
+                Box::new(first.clone()),
+                Box::new(FailingSender {}),
+                Box::new(second.clone()),
+            ],
+            channels,
+        );
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+        // The failing sender doesn't keep the message from the sender after it.
+        assert_eq!(
+            sender.deliver(&target, "Curator".to_string(), &target.uri),
+            vec![
+                ("fake".to_string(), Ok(())),
+                (
+                    "failing".to_string(),
+                    Err(LmkError::Notify("unreachable".to_string()))
+                ),
+                ("fake".to_string(), Ok(())),
+            ]
+        );
+        let want = vec![
+            "[to everyone@everyone.com] Target https://museum.org/jobs. msg: \n Curator"
+                .to_string(),
+        ];
+        assert_eq!(*first.lock().unwrap().msgs.borrow(), want);
+        assert_eq!(*second.lock().unwrap().msgs.borrow(), want);
+        assert_eq!(sender.name(), "fake,failing,fake");
+        assert!(sender
+            .send(
+                "everyone@everyone.com",
+                &target,
+                "Curator".to_string(),
+                &target.uri
+            )
+            .is_err());
+        assert_eq!(first.lock().unwrap().msgs.borrow().len(), 2);
+        assert_eq!(second.lock().unwrap().msgs.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_composite_sender_concurrency() {
+        // Waits for the message of the other sender before sending its own.
+        struct WaitingSender {
+            signal: Mutex<mpsc::Receiver<()>>,
+        }
+        impl Sender for WaitingSender {
+            fn name(&self) -> String {
+                "waiting".to_string()
+            }
+
+            fn send(
+                &self,
+                _addr: &str,
+                _t: &Target,
+                _msg: String,
+                _link: &str,
+            ) -> Result<(), LmkError> {
+                self.signal
+                    .lock()
+                    .unwrap()
+                    .recv_timeout(Duration::from_secs(5))
+                    .map_err(|e| LmkError::Notify(e.to_string()))
+            }
+        }
+        struct SignalingSender {
+            signal: Mutex<mpsc::Sender<()>>,
+        }
+        impl Sender for SignalingSender {
+            fn name(&self) -> String {
+                "signaling".to_string()
+            }
+
+            fn send(
+                &self,
+                _addr: &str,
+                _t: &Target,
+                _msg: String,
+                _link: &str,
+            ) -> Result<(), LmkError> {
+                self.signal
+                    .lock()
+                    .unwrap()
+                    .send(())
+                    .map_err(|e| LmkError::Notify(e.to_string()))
+            }
         }
+
+        let (tx, rx) = mpsc::channel();
+        let mut channels = Channels::default();
+        channels.set_default_addr("waiting", "everyone@everyone.com".to_string());
+        channels.set_default_addr("signaling", "everyone@everyone.com".to_string());
+        let sender = CompositeSender::new(
+            vec![
+                Box::new(WaitingSender {
+                    signal: Mutex::new(rx),
+                }),
+                Box::new(SignalingSender {
+                    signal: Mutex::new(tx),
+                }),
+            ],
+            channels,
+        );
+        let target = Target::default();
+        // Sent one after the other, the waiting sender would time out.
+        assert_eq!(
+            sender.deliver(&target, "Curator".to_string(), ""),
+            vec![
+                ("waiting".to_string(), Ok(())),
+                ("signaling".to_string(), Ok(())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_composite_sender_routing() -> Result<(), Box<dyn std::error::Error>> {
+        let fake = Arc::new(Mutex::new(FakeSender::new()));
+        let mut channels: Channels = serde_yaml::from_str(
+            r#"
+contemporary:
+  fake: [curator@museum.org, registrar@museum.org]
+photography:
+  fake: registrar@museum.org
+"#,
+        )?;
+        channels.set_default_addr("fake", "everyone@everyone.com".to_string());
+        let sender = CompositeSender::new(vec![Box::new(fake.clone())], channels);
+        let mut target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+        sender.send(
+            "everyone@everyone.com",
+            &target,
+            "Curator".to_string(),
+            &target.uri,
+        )?;
+        target.channels = vec!["contemporary".to_string(), "photography".to_string()];
+        sender.send(
+            "everyone@everyone.com",
+            &target,
+            "Curator".to_string(),
+            &target.uri,
+        )?;
+        let addrs: Vec<_> = fake
+            .lock()
+            .unwrap()
+            .msgs
+            .borrow()
+            .iter()
+            .map(|x| x.split(']').next().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            addrs,
+            vec![
+                "[to everyone@everyone.com",
+                "[to curator@museum.org",
+                "[to registrar@museum.org"
+            ]
+        );
+
+        // Backends without an address for the target fail rather than send nothing.
+        let sender = CompositeSender::new(vec![Box::new(fake.clone())], Channels::default());
+        assert_eq!(
+            sender.deliver(&target, "Curator".to_string(), &target.uri),
+            vec![(
+                "fake".to_string(),
+                Err(LmkError::Notify(
+                    "no fake address for target https://museum.org/jobs".to_string()
+                ))
+            )]
+        );
+        assert_eq!(fake.lock().unwrap().msgs.borrow().len(), 3);
+        Ok(())
     }
 
     #[test]
@@ -433,17 +1900,104 @@ mod tests {
         "#,
         );
         // The first scrape should give us one matching meow.
-        scraper.handle_page_content(html.clone(), &target)?;
+        scraper.handle_page_content(vec![html.clone()], &target, &[])?;
         assert_eq!(sender.msgs.borrow().len(), 2);
 
         // run again after deleting the cache , should have another match.
         let target_id = Scraper::<FakeSender>::target_id(&target);
         scraper.target_cache.borrow_mut().put(&target_id, "")?;
-        scraper.handle_page_content(html.clone(), &target)?;
+        scraper.handle_page_content(vec![html.clone()], &target, &[])?;
         assert_eq!(sender.msgs.borrow().len(), 4);
         Ok(())
     }
 
+    #[test]
+    fn test_undelivered_matches() -> Result<(), Box<dyn std::error::Error>> {
+        // Fails while down is set.
+        struct FlakySender {
+            down: std::cell::Cell<bool>,
+            fake: FakeSender,
+        }
+        impl Sender for FlakySender {
+            fn name(&self) -> String {
+                "flaky".to_string()
+            }
+
+            fn send(
+                &self,
+                addr: &str,
+                t: &Target,
+                msg: String,
+                link: &str,
+            ) -> Result<(), LmkError> {
+                if self.down.get() {
+                    return Err(LmkError::Notify("unreachable".to_string()));
+                }
+                self.fake.send(addr, t, msg, link)
+            }
+        }
+
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+        let sender = FlakySender {
+            down: std::cell::Cell::new(true),
+            fake: FakeSender::new(),
+        };
+        let scraper = Scraper::new_in_memory(vec![], &sender);
+        let html = Html::parse_document("<li>Curator</li><li>Registrar</li>");
+        scraper.handle_page_content(vec![html.clone()], &target, &[])?;
+        assert!(sender.fake.msgs.borrow().is_empty());
+
+        // The undelivered match is reported once the sender is back up, and only once.
+        sender.down.set(false);
+        scraper.handle_page_content(vec![html.clone()], &target, &[])?;
+        scraper.handle_page_content(vec![html], &target, &[])?;
+        assert_eq!(
+            *sender.fake.msgs.borrow(),
+            vec!["[to default] Target https://museum.org/jobs. msg: \n Found match: Curator"]
+        );
+        // The match history has the match once, along with when it was first seen.
+        let target_id = Scraper::<FlakySender>::target_id(&target);
+        assert_eq!(
+            scraper.target_cache.borrow().matches(&target_id),
+            vec![("Curator".to_string(), String::new())]
+        );
+
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            mode: TargetMode::Diff,
+            ..Default::default()
+        };
+        scraper.handle_page_diff(vec![Html::parse_document("<p>Curator</p>")], &target, "")?;
+        sender.down.set(true);
+        let changed = Html::parse_document("<p>Assistant Curator</p>");
+        scraper.handle_page_diff(vec![changed.clone()], &target, "")?;
+        sender.down.set(false);
+        scraper.handle_page_diff(vec![changed], &target, "")?;
+        assert_eq!(sender.fake.msgs.borrow().len(), 2);
+        assert!(sender.fake.msgs.borrow()[1].contains("+Assistant Curator"));
+
+        let target = Target {
+            uri: "https://museum.org/visitors".to_string(),
+            mode: TargetMode::Threshold,
+            above: Some(100.0),
+            ..Default::default()
+        };
+        scraper.handle_page_number(vec![Html::parse_document("<p>90</p>")], &target, "")?;
+        sender.down.set(true);
+        let crowded = Html::parse_document("<p>120</p>");
+        scraper.handle_page_number(vec![crowded.clone()], &target, "")?;
+        sender.down.set(false);
+        scraper.handle_page_number(vec![crowded.clone()], &target, "")?;
+        scraper.handle_page_number(vec![crowded], &target, "")?;
+        assert_eq!(sender.fake.msgs.borrow().len(), 3);
+        assert!(sender.fake.msgs.borrow()[2].contains("Value 120 rose above 100 (was 90)"));
+        Ok(())
+    }
+
     #[test]
     fn test_handle_page_content_caches() -> Result<(), Box<dyn std::error::Error>> {
         let target = Target {
@@ -459,7 +2013,7 @@ mod tests {
          <li> cactus </li>
         "#,
         );
-        scraper.handle_page_content(html.clone(), &target)?;
+        scraper.handle_page_content(vec![html.clone()], &target, &[])?;
         // One message for the meow.
         assert_eq!(sender.msgs.borrow().len(), 1);
         // let's update the html to include a new element. A message should only be added for the
@@ -471,7 +2025,7 @@ mod tests {
          <li> another meow!!!! </li>
         "#,
         );
-        scraper.handle_page_content(html.clone(), &target)?;
+        scraper.handle_page_content(vec![html.clone()], &target, &[])?;
         // Only an additional message should be appended.
         assert_eq!(sender.msgs.borrow().len(), 2);
         // New message should be different than the first.
@@ -480,38 +2034,148 @@ mod tests {
     }
 
     #[test]
-    fn test_real_http_server() -> Result<(), Box<dyn std::error::Error>> {
-        let server = httptest::Server::run();
-
-        server.expect(
-            Expectation::matching(request::method_path("GET", "/target1"))
-                .times(..) // any number.
-                .respond_with(status_code(200).body("meow-meow")),
-        );
-        server.expect(
-            Expectation::matching(request::method_path("GET", "/target2"))
-                .times(..)
-                .respond_with(status_code(200).body("cat-meow")),
-        );
-        server.expect(
-            Expectation::matching(request::method_path("GET", "/i-don't-exist"))
-                .times(..)
-                .respond_with(status_code(404)),
+    fn test_message_template() -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            description: "Museum curators".to_string(),
+            message_template: Some(Template::parse(
+                "{description}: {match} ({link}, first seen {first_seen})",
+            )?),
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![], &sender);
+        let target_id = Scraper::<FakeSender>::target_id(&target);
+        // The match was seen by an earlier run that failed to report it.
+        scraper
+            .target_cache
+            .borrow_mut()
+            .add_match(&target_id, "Assistant Curator", "")?;
+        scraper
+            .target_cache
+            .borrow_mut()
+            .backdate_matches(&target_id, 24 * 60 * 60)?;
+        let first_seen = scraper
+            .target_cache
+            .borrow()
+            .first_seen(&target_id, "Assistant Curator")
+            .ok_or("not recorded")?;
+        let first_seen = Utc
+            .timestamp_opt(first_seen as i64, 0)
+            .single()
+            .ok_or("invalid timestamp")?;
+        let html = Html::parse_document("<li>Assistant Curator</li>");
+        scraper.handle_page_content(vec![html], &target, &[])?;
+        assert_eq!(
+            *sender.msgs.borrow(),
+            vec![format!(
+                "[to default] Target https://museum.org/jobs. msg: \n Museum curators: Assistant Curator (https://museum.org/jobs, first seen {})",
+                first_seen.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )]
         );
 
-        let target1 = Target {
-            uri: server.url_str("/target1"),
-            text: "meow".to_string(),
-            ..Default::default()
+        // New pages are looked up by the same key they're recorded with.
+        let target = Target {
+            kind: TargetKind::Crawl,
+            message_template: Some(Template::parse("{match} {link} {first_seen}")?),
+            ..target
         };
-        let target2 = Target {
-            uri: server.url_str("/target2"),
-            text: "cat".to_string(),
+        let page = Response {
+            body: "<li>Assistant Curator</li>".to_string(),
             ..Default::default()
         };
-        let target3 = Target {
-            uri: server.url_str("/i-don't-exist"),
-            text: "cactus".to_string(),
+        scraper.handle_crawled_pages(
+            vec![("https://museum.org/jobs/1".to_string(), page)],
+            &target,
+        )?;
+        let key = "https://museum.org/jobs/1 (Assistant Curator)";
+        let first_seen = scraper
+            .target_cache
+            .borrow()
+            .first_seen(&target_id, key)
+            .ok_or("not recorded")?;
+        assert_eq!(
+            sender.msgs.borrow()[1],
+            format!(
+                "[to default] Target https://museum.org/jobs. msg: \n Assistant Curator https://museum.org/jobs/1 {}",
+                Utc.timestamp_opt(first_seen as i64, 0)
+                    .single()
+                    .ok_or("invalid timestamp")?
+                    .to_rfc3339_opts(SecondsFormat::Secs, true)
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_page_content_job_postings() -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target {
+            uri: "test_handle_page_content_job_postings".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![], &sender);
+        let html = Html::parse_document(
+            r#"
+            <html><head>
+            <script type="application/ld+json">
+              {"@type": "JobPosting", "title": "Assistant Curator", "url": "https://museum.org/1",
+               "validThrough": "2022-12-01"}
+            </script>
+            </head><body>
+            <h2>Assistant Curator</h2>
+            <p>Curatorial fellowships coming soon</p>
+            </body></html>
+        "#,
+        );
+        scraper.handle_page_content(vec![html.clone()], &target, &[])?;
+        // One structured match for the posting and one for the plain text, the json-ld source and
+        // the heading repeating the posting title aren't reported.
+        assert_eq!(sender.msgs.borrow().len(), 2);
+        assert!(sender.msgs.borrow()[0].contains("Found match: Curatorial fellowships"));
+        assert!(sender.msgs.borrow()[1]
+            .contains("Found posting: Assistant Curator https://museum.org/1 apply by 2022-12-01"));
+
+        scraper.handle_page_content(vec![html], &target, &[])?;
+        assert_eq!(sender.msgs.borrow().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_real_http_server() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/target1"))
+                .times(..) // any number.
+                .respond_with(status_code(200).body("meow-meow")),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/target2"))
+                .times(..)
+                .respond_with(status_code(200).body("cat-meow")),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/i-don't-exist"))
+                .times(..)
+                .respond_with(status_code(404)),
+        );
+
+        let target1 = Target {
+            uri: server.url_str("/target1"),
+            text: "meow".to_string(),
+            ..Default::default()
+        };
+        let target2 = Target {
+            uri: server.url_str("/target2"),
+            text: "cat".to_string(),
+            ..Default::default()
+        };
+        let target3 = Target {
+            uri: server.url_str("/i-don't-exist"),
+            text: "cactus".to_string(),
             ..Default::default()
         };
         let sender = FakeSender::new();
@@ -592,6 +2256,1023 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_board_targets() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/greenhouse"))
+                .times(..)
+                .respond_with(cycle![
+                    status_code(200).body(
+                        r#"{"jobs": [
+                            {"id": 1, "title": "Assistant Curator", "absolute_url": "https://gh/1"},
+                            {"id": 2, "title": "Guard", "absolute_url": "https://gh/2"}
+                        ]}"#
+                    ),
+                    // The posting got renamed and a new one was added.
+                    status_code(200).body(
+                        r#"{"jobs": [
+                            {"id": 1, "title": "Assistant Curator (Photography)", "absolute_url": "https://gh/1"},
+                            {"id": 3, "title": "Curator", "absolute_url": "https://gh/3"}
+                        ]}"#
+                    ),
+                ]),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/lever"))
+                .times(..)
+                .respond_with(status_code(200).body(
+                    r#"[{"id": "abc", "text": "Curator", "hostedUrl": "https://lever/abc"}]"#,
+                )),
+        );
+
+        let greenhouse = Target {
+            uri: server.url_str("/greenhouse"),
+            text: "Curator".to_string(),
+            kind: TargetKind::Greenhouse,
+            ..Default::default()
+        };
+        let lever = Target {
+            uri: server.url_str("/lever"),
+            text: "Curator".to_string(),
+            kind: TargetKind::Lever,
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![greenhouse, lever], &sender);
+
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 2);
+        assert!(sender
+            .msgs
+            .borrow()
+            .iter()
+            .any(|x| x.contains("https://gh/1")));
+        assert!(sender
+            .msgs
+            .borrow()
+            .iter()
+            .any(|x| x.contains("https://lever/abc")));
+
+        // Only the posting with the new id is reported, the renamed one is already known.
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 3);
+        assert!(sender.msgs.borrow()[2].contains("https://gh/3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_board_target_fetch_uri() -> Result<(), Box<dyn std::error::Error>> {
+        let target: Target =
+            serde_yaml::from_str("kind: greenhouse\nboard: museum\ntext: Curator")?;
+        assert_eq!(target.kind, TargetKind::Greenhouse);
+        assert_eq!(
+            target.fetch_uri(),
+            "https://boards-api.greenhouse.io/v1/boards/museum/jobs?content=true"
+        );
+        let target: Target = serde_yaml::from_str("kind: lever\nboard: icp\ntext: Curator")?;
+        assert_eq!(
+            target.fetch_uri(),
+            "https://api.lever.co/v0/postings/icp?mode=json"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pagination_next_page() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs"))
+                .times(..)
+                .respond_with(
+                    status_code(200)
+                        .body(r#"<li>Curator 1</li><a class="next" href="/jobs/2">Next</a>"#),
+                ),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs/2"))
+                .times(..)
+                .respond_with(
+                    status_code(200).body(r#"<li>Curator 2</li><a class="next" href="3">Next</a>"#),
+                ),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs/3"))
+                .times(..)
+                .respond_with(status_code(200).body(
+                    // Links back to the first page, which was already visited.
+                    r#"<li>Curator 3</li><a class="next" href="/jobs">Next</a>"#,
+                )),
+        );
+
+        let target = Target {
+            uri: server.url_str("/jobs"),
+            text: "Curator".to_string(),
+            next_page: "a.next".to_string(),
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![target], &sender);
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 3);
+        for i in 1..=3 {
+            assert!(sender.msgs.borrow()[i - 1].contains(&format!("Curator {}", i)));
+        }
+        // All of the pages share the target's cache entry.
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pagination_page_template() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs"))
+                .times(..)
+                .respond_with(status_code(200).body("<li>Curator 1</li>")),
+        );
+        for i in 2..=3 {
+            server.expect(
+                Expectation::matching(request::method_path("GET", format!("/jobs/page/{}", i)))
+                    .times(..)
+                    .respond_with(status_code(200).body(format!("<li>Curator {}</li>", i))),
+            );
+        }
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs/page/4"))
+                .times(..)
+                .respond_with(status_code(404)),
+        );
+
+        let mut target = Target {
+            uri: server.url_str("/jobs"),
+            text: "Curator".to_string(),
+            page_template: server.url_str("/jobs/page/{page}"),
+            ..Default::default()
+        };
+        let mut span = global::tracer("test").start("test");
+        let pages = Scraper::<FakeSender>::fetch_pages(&reqwest_options(), &target, &mut span)?;
+        // The 404 ends the pagination.
+        assert_eq!(pages.len(), 3);
+
+        target.max_pages = Some(2);
+        let pages = Scraper::<FakeSender>::fetch_pages(&reqwest_options(), &target, &mut span)?;
+        let bodies: Vec<_> = pages.iter().map(|x| x.body.as_str()).collect();
+        assert_eq!(bodies, vec!["<li>Curator 1</li>", "<li>Curator 2</li>"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawl_target() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs"))
+                .times(2)
+                .respond_with(cycle![
+                    status_code(200).body(r#"<a href="/jobs/1">1</a><a href="/about">about</a>"#),
+                    status_code(200).body(r#"<a href="/jobs/1">1</a><a href="/jobs/2">2</a>"#),
+                ]),
+        );
+        // Each posting is only fetched the first time it's linked.
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs/1"))
+                .times(1)
+                .respond_with(status_code(200).body("<h1>Assistant Curator</h1>")),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs/2"))
+                .times(1)
+                .respond_with(status_code(200).body("<h1>Guard</h1>")),
+        );
+
+        let target = Target {
+            uri: server.url_str("/jobs"),
+            text: "Curator".to_string(),
+            kind: TargetKind::Crawl,
+            follow: r"/jobs/\d+$".to_string(),
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![target], &sender);
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+        assert!(sender.msgs.borrow()[0].contains(&format!(
+            "Found new page: {} (Assistant Curator)",
+            server.url_str("/jobs/1")
+        )));
+        assert_eq!(*sender.links.borrow(), vec![server.url_str("/jobs/1")]);
+
+        // /jobs/2 is new but doesn't match.
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawl_seen_pages() -> Result<(), Box<dyn std::error::Error>> {
+        let fetcher = FakeFetcher::default()
+            .page(
+                "https://museum.org/jobs",
+                r#"<a href="/jobs/curatorial">Curatorial</a><a href="/jobs/1">1</a>"#,
+            )
+            .page(
+                "https://museum.org/jobs/curatorial",
+                r#"<a href="/jobs/2">2</a>"#,
+            )
+            .page("https://museum.org/jobs/1", "<h1>Curator</h1>")
+            .page("https://museum.org/jobs/2", "<h1>Assistant Curator</h1>");
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            kind: TargetKind::Crawl,
+            follow: "/jobs/".to_string(),
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let options = FetchOptions::new(fetcher);
+        let seen = HashSet::from([
+            "https://museum.org/jobs/curatorial".to_string(),
+            "https://museum.org/jobs/1".to_string(),
+        ]);
+        let mut span = global::tracer("test").start("test");
+        let pages = Scraper::<FakeSender, FakeFetcher>::crawl(&options, &target, &seen, &mut span)?;
+        // The seen pages are followed to the new posting, but only the new posting is reported.
+        assert_eq!(
+            pages
+                .iter()
+                .map(|(url, _)| url.as_str())
+                .collect::<Vec<_>>(),
+            vec!["https://museum.org/jobs/2"]
+        );
+        assert_eq!(
+            options.fetcher.requested_uris(),
+            vec![
+                "https://museum.org/jobs",
+                "https://museum.org/jobs/curatorial",
+                "https://museum.org/jobs/1",
+                "https://museum.org/jobs/2"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawl_sitemap() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/sitemap.xml")).respond_with(
+                status_code(200).body(format!(
+                    "<urlset><url><loc>{}</loc></url><url><loc>{}</loc></url></urlset>",
+                    server.url_str("/jobs/1"),
+                    server.url_str("/events/1"),
+                )),
+            ),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs/1"))
+                .respond_with(status_code(200).body("<h1>Curator</h1>")),
+        );
+
+        let target = Target {
+            uri: server.url_str("/sitemap.xml"),
+            kind: TargetKind::Crawl,
+            follow: "/jobs/".to_string(),
+            ..Default::default()
+        };
+        let mut span = global::tracer("test").start("test");
+        let pages =
+            Scraper::<FakeSender>::crawl(&reqwest_options(), &target, &HashSet::new(), &mut span)?;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].0, server.url_str("/jobs/1"));
+        assert_eq!(pages[0].1.body, "<h1>Curator</h1>");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_mode() -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target {
+            uri: "test_diff_mode".to_string(),
+            mode: TargetMode::Diff,
+            selector: "#positions".to_string(),
+            min_change: 2,
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![], &sender);
+        let page = |positions: &str, footer: &str| {
+            Html::parse_document(&format!(
+                r#"<ul id="positions">{}</ul><footer>{}</footer>"#,
+                positions, footer
+            ))
+        };
+
+        // The first run only records the content.
+        scraper.handle_page_diff(vec![page("<li>Curator</li>", "2022")], &target, "")?;
+        assert_eq!(sender.msgs.borrow().len(), 0);
+
+        // Changes outside of the selected region and markup changes are ignored.
+        scraper.handle_page_diff(vec![page("<li><b>Curator</b></li>", "2023")], &target, "")?;
+        assert_eq!(sender.msgs.borrow().len(), 0);
+
+        // A one line addition is below min_change.
+        scraper.handle_page_diff(
+            vec![page("<li>Curator</li><li>Registrar</li>", "")],
+            &target,
+            "",
+        )?;
+        assert_eq!(sender.msgs.borrow().len(), 0);
+
+        // But accumulates with the next change.
+        scraper.handle_page_diff(
+            vec![page("<li>Curator</li><li>Registrar</li><li>Guard</li>", "")],
+            &target,
+            "",
+        )?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+        assert!(sender.msgs.borrow()[0].contains("Content changed (2 lines)"));
+        assert!(sender.msgs.borrow()[0].contains("+Registrar\n+Guard"));
+
+        scraper.handle_page_diff(
+            vec![page("<li>Curator</li><li>Registrar</li><li>Guard</li>", "")],
+            &target,
+            "",
+        )?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_threshold_mode() -> Result<(), Box<dyn std::error::Error>> {
+        let target: Target = serde_yaml::from_str(
+            r##"
+            uri: test_threshold_mode
+            text: ""
+            mode: threshold
+            selector: "#count"
+            regex: "(\\d+) open"
+            below: 3
+            change_percent: 40
+            "##,
+        )?;
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![], &sender);
+        let page = |count: usize| {
+            Html::parse_document(&format!(
+                r#"<h1>Since 2022</h1><p id="count">We have {} open positions</p>"#,
+                count
+            ))
+        };
+
+        scraper.handle_page_number(vec![page(5)], &target, "")?;
+        assert_eq!(sender.msgs.borrow().len(), 0);
+        scraper.handle_page_number(vec![page(4)], &target, "")?;
+        assert_eq!(sender.msgs.borrow().len(), 0);
+        scraper.handle_page_number(vec![page(2)], &target, "")?;
+        assert_eq!(sender.msgs.borrow().len(), 2);
+        assert!(sender.msgs.borrow()[0].contains("Value 2 dropped below 3 (was 4)"));
+        assert!(sender.msgs.borrow()[1].contains("Value changed by -50.0% from 4 to 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_uris() -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target {
+            uri: testdata_uri("www.newmuseum.org.html"),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+        let missing = Target {
+            uri: testdata_uri("i-don't-exist.html"),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+        let relative = Target {
+            uri: "file://testdata/www.newmuseum.org.html".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+        let mut span = global::tracer("test").start("test");
+        let options = reqwest_options();
+        assert!(Scraper::<FakeSender>::fetch_pages(&options, &missing, &mut span).is_err());
+        assert!(Scraper::<FakeSender>::fetch_pages(&options, &relative, &mut span).is_err());
+
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![target, missing], &sender);
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+        assert!(sender.msgs.borrow()[0].contains("Found match: Curator"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stdin() -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target {
+            uri: "https://museum.org/careers".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let mut scraper = Scraper::new_in_memory(vec![target], &sender);
+        scraper.fetch_options = FetchOptions {
+            stdin: Some("<li>Assistant Curator</li>".to_string()),
+            ..reqwest_options()
+        };
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+        assert!(sender.msgs.borrow()[0].contains("https://museum.org/careers"));
+        assert!(sender.msgs.borrow()[0].contains("Found match: Assistant Curator"));
+
+        // Runs on stdin don't write metrics.
+        let options = FetchOptions {
+            stdin: Some("<li>Assistant Curator</li>".to_string()),
+            ..reqwest_options()
+        };
+        let scraper = Scraper::new(vec![], &sender, ":memory:", options)?;
+        assert!(scraper.metrics.log_writer.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_replay() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("lmk-test-record-replay-{}", std::process::id()));
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs"))
+                .respond_with(status_code(200).body(r#"<li>Curator</li><a href="2">next</a>"#)),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/2"))
+                .respond_with(status_code(200).body("<li>Assistant Curator</li>")),
+        );
+        let target = || Target {
+            uri: server.url_str("/jobs"),
+            text: "Curator".to_string(),
+            next_page: "a".to_string(),
+            ..Default::default()
+        };
+
+        let sender = FakeSender::new();
+        let mut scraper = Scraper::new_in_memory(vec![target()], &sender);
+        scraper.fetch_options.record = Some(dir.clone());
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 2);
+
+        // The server only answers once, the replayed run doesn't hit it.
+        let replay_sender = FakeSender::new();
+        let mut scraper = Scraper::new_in_memory(vec![target()], &replay_sender);
+        scraper.fetch_options.replay = Some(dir.clone());
+        scraper.scrape()?;
+        assert_eq!(*replay_sender.msgs.borrow(), *sender.msgs.borrow());
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_warc_archival() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("lmk-test-warc-archival-{}", std::process::id()));
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs"))
+                .respond_with(status_code(200).body("<li>Curator</li><li>Registrar</li>")),
+        );
+        let target = Target {
+            uri: server.url_str("/jobs"),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+
+        let target_id = Scraper::<FakeSender>::target_id(&target);
+        let sender = FakeSender::new();
+        let mut scraper = Scraper::new_in_memory(vec![target], &sender);
+        scraper.fetch_options.warc = Some(WarcWriter::new(dir.clone(), 1 << 20));
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+
+        let matches = scraper.target_cache.borrow().matches(&target_id);
+        assert_eq!(matches.len(), 1);
+        let (m, warc_record_ids) = &matches[0];
+        assert_eq!(m, "Curator");
+        assert!(warc_record_ids.starts_with("<urn:uuid:"));
+        let files: Vec<_> = std::fs::read_dir(&dir)?.collect::<Result<_, _>>()?;
+        let contents = std::fs::read_to_string(files[0].path())?;
+        assert!(contents.contains(&format!("WARC-Record-ID: {}\r\n", warc_record_ids)));
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_warc_record_ids() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("lmk-test-warc-record-ids-{}", std::process::id()));
+        let fetcher = FakeFetcher::default()
+            .page("https://museum.org/jobs", "<li>Curator</li>")
+            .page("https://museum.org/jobs?page=2", "<li>Registrar</li>")
+            .page("https://museum.org/visitors", "<p>10 visitors</p>");
+        let jobs = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "r".to_string(),
+            page_template: "https://museum.org/jobs?page={page}".to_string(),
+            max_pages: Some(2),
+            ..Default::default()
+        };
+        let visitors = Target {
+            uri: "https://museum.org/visitors".to_string(),
+            mode: TargetMode::Threshold,
+            above: Some(5.0),
+            ..Default::default()
+        };
+        let jobs_id = Scraper::<FakeSender, FakeFetcher>::target_id(&jobs);
+        let visitors_id = Scraper::<FakeSender, FakeFetcher>::target_id(&visitors);
+        let sender = FakeSender::new();
+        let mut scraper = Scraper::with_fetcher(vec![jobs, visitors], &sender, fetcher);
+        scraper.fetch_options.warc = Some(WarcWriter::new(dir.clone(), 1 << 20));
+        scraper.scrape()?;
+
+        // Each match is linked to the page it was found on.
+        let matches = scraper.target_cache.borrow().matches(&jobs_id);
+        assert_eq!(
+            matches.iter().map(|(m, _)| m.as_str()).collect::<Vec<_>>(),
+            vec!["Curator", "Registrar"]
+        );
+        let (curator_id, registrar_id) = (&matches[0].1, &matches[1].1);
+        assert!(curator_id.starts_with("<urn:uuid:") && !curator_id.contains(' '));
+        assert!(registrar_id.starts_with("<urn:uuid:") && !registrar_id.contains(' '));
+        assert_ne!(curator_id, registrar_id);
+        // Threshold (and diff) alerts are linked too.
+        let matches = scraper.target_cache.borrow().matches(&visitors_id);
+        assert_eq!(matches.len(), 1);
+        let contents: Vec<_> = std::fs::read_dir(&dir)?
+            .map(|x| std::fs::read_to_string(x?.path()))
+            .collect::<Result<_, _>>()?;
+        let record = format!("WARC-Record-ID: {}\r\n", matches[0].1);
+        assert!(contents.iter().any(|x| x.contains(&record)));
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_pdf_links() -> Result<(), Box<dyn std::error::Error>> {
+        let pdf = std::fs::read("testdata/job-description.pdf")?;
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jobs"))
+                .times(2)
+                .respond_with(status_code(200).body(
+                    r#"<a href="/jd/curator.pdf">Job description</a>
+                       <a href="/jd/huge.pdf">Other</a>
+                       <a href="/about.pdf">About</a>"#,
+                )),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("GET", "/jd/curator.pdf"),
+                request::headers(not(contains(key("if-none-match")))),
+            ])
+            .respond_with(
+                status_code(200)
+                    .insert_header("etag", "\"v1\"")
+                    .body(pdf.clone()),
+            ),
+        );
+        // The second run only revalidates the cached PDF.
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("GET", "/jd/curator.pdf"),
+                request::headers(contains(("if-none-match", "\"v1\""))),
+            ])
+            .respond_with(status_code(304)),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/jd/huge.pdf"))
+                .times(2)
+                .respond_with(status_code(200).body(vec![b'%'; 2000])),
+        );
+        let target = Target {
+            uri: server.url_str("/jobs"),
+            text: "Assistant Curator".to_string(),
+            pdf_links: r"/jd/.*\.pdf$".to_string(),
+            max_pdf_bytes: Some(1000),
+            ..Default::default()
+        };
+
+        let sender = FakeSender::new();
+        let scraper = Scraper::new_in_memory(vec![target], &sender);
+        scraper.scrape()?;
+        let pdf_url = server.url_str("/jd/curator.pdf");
+        assert_eq!(
+            *sender.msgs.borrow(),
+            vec![
+                format!(
+                    "[to default] Target {}. msg: \n Found match in {}: Assistant Curator of Photography",
+                    server.url_str("/jobs"),
+                    pdf_url
+                ),
+                format!(
+                    "[to default] Target {}. msg: \n Found match in {}: The Museum seeks an Assistant Curator to join the Department of Photography.",
+                    server.url_str("/jobs"),
+                    pdf_url
+                ),
+            ]
+        );
+
+        sender.msgs.borrow_mut().clear();
+        scraper.scrape()?;
+        assert!(sender.msgs.borrow().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pdf_links_record_replay() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("lmk-test-pdf-replay-{}", std::process::id()));
+        let fetcher = FakeFetcher::default()
+            .page(
+                "https://museum.org/jobs",
+                r#"<a href="/jd/curator.pdf">Job description</a>"#,
+            )
+            .respond(
+                "https://museum.org/jd/curator.pdf",
+                Ok(Response {
+                    status: 200,
+                    data: std::fs::read("testdata/job-description.pdf")?,
+                    ..Default::default()
+                }),
+            );
+        let target = || Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Assistant Curator of".to_string(),
+            pdf_links: r"\.pdf$".to_string(),
+            ..Default::default()
+        };
+
+        let sender = FakeSender::new();
+        let mut scraper = Scraper::with_fetcher(vec![target()], &sender, fetcher);
+        scraper.fetch_options.record = Some(dir.clone());
+        scraper.scrape()?;
+        let requests = scraper
+            .fetch_options
+            .fetcher
+            .requests
+            .lock()
+            .unwrap()
+            .clone();
+        assert!(requests[1].binary);
+        assert_eq!(requests[1].max_bytes, Some(Target::DEFAULT_MAX_PDF_BYTES));
+        assert_eq!(sender.msgs.borrow().len(), 1);
+
+        // PDFs are replayed along with the pages.
+        let replay_sender = FakeSender::new();
+        let mut scraper = Scraper::new_in_memory(vec![target()], &replay_sender);
+        scraper.fetch_options.replay = Some(dir.clone());
+        scraper.scrape()?;
+        assert_eq!(*replay_sender.msgs.borrow(), *sender.msgs.borrow());
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_session() -> Result<(), Box<dyn std::error::Error>> {
+        std::env::set_var("LMK_TEST_LOGIN_PASSWORD", "s3cret");
+        let server = httptest::Server::run();
+        // Logs in on the first run and once the cookie expired.
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/login"),
+                request::body(url_decoded(contains(("user", "curator")))),
+                request::body(url_decoded(contains(("password", "s3cret")))),
+            ])
+            .times(2)
+            .respond_with(
+                status_code(302)
+                    .insert_header("location", "/members/jobs")
+                    .insert_header("set-cookie", "sid=abc; Path=/; HttpOnly"),
+            ),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("GET", "/members/jobs"),
+                request::headers(contains(("cookie", "sid=abc"))),
+            ])
+            .times(3)
+            .respond_with(status_code(200).body("<li>Assistant Curator</li>")),
+        );
+        // Targets without the session don't get its cookies, even on the same host.
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("GET", "/jobs"),
+                request::headers(not(contains(key("cookie")))),
+            ])
+            .times(3)
+            .respond_with(status_code(200).body("<li>Registrar</li>")),
+        );
+        let target = Target {
+            uri: server.url_str("/members/jobs"),
+            text: "Curator".to_string(),
+            session: "members".to_string(),
+            ..Default::default()
+        };
+        let public = Target {
+            uri: server.url_str("/jobs"),
+            text: "Curator".to_string(),
+            ..Default::default()
+        };
+        let sender = FakeSender::new();
+        let mut scraper = Scraper::new_in_memory(vec![target, public], &sender);
+        scraper.fetch_options.sessions = HashMap::from([(
+            "members".to_string(),
+            Session {
+                login_uri: server.url_str("/login"),
+                form: [
+                    ("user".to_string(), "curator".to_string()),
+                    (
+                        "password".to_string(),
+                        "${LMK_TEST_LOGIN_PASSWORD}".to_string(),
+                    ),
+                ]
+                .into(),
+                ..Default::default()
+            },
+        )]);
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+
+        // The cookie jar is reused from the cache.
+        scraper.scrape()?;
+        let jar = scraper
+            .target_cache
+            .borrow()
+            .get("session:members")
+            .unwrap();
+        let mut jar: Vec<Cookie> = serde_json::from_str(&jar)?;
+        assert_eq!(jar.len(), 1);
+        assert_eq!(jar[0].value, "abc");
+
+        jar[0].expires = 1;
+        scraper
+            .target_cache
+            .borrow_mut()
+            .put("session:members", &serde_json::to_string(&jar)?)?;
+        scraper.scrape()?;
+        assert_eq!(sender.msgs.borrow().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_renderer() -> Result<(), Box<dyn std::error::Error>> {
+        // Stands in for the rendering service.
+        let renderer = httptest::Server::run();
+        renderer.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/content"),
+                request::body(json_decoded(eq(serde_json::json!({
+                    "url": "https://www.guggenheim.org/careers",
+                    "cookies": [{
+                        "name": "sid", "value": "abc", "domain": "www.guggenheim.org", "path": "/",
+                    }],
+                })))),
+            ])
+            .respond_with(status_code(200).body("<li>Assistant Curator</li>")),
+        );
+        renderer.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/content"),
+                request::body(json_decoded(eq(serde_json::json!({
+                    "url": "https://www.guggenheim.org/broken",
+                })))),
+            ])
+            .respond_with(status_code(500)),
+        );
+        let sender = FakeSender::new();
+        let mut scraper = Scraper::new_in_memory(
+            vec![
+                Target {
+                    uri: "https://www.guggenheim.org/careers".to_string(),
+                    text: "Curator".to_string(),
+                    render: true,
+                    session: "members".to_string(),
+                    ..Default::default()
+                },
+                Target {
+                    uri: "https://www.guggenheim.org/broken".to_string(),
+                    text: "Curator".to_string(),
+                    render: true,
+                    ..Default::default()
+                },
+            ],
+            &sender,
+        );
+        scraper.fetch_options.renderer = Some(Renderer {
+            endpoint: renderer.url_str("/content"),
+        });
+        let url = Url::parse("https://www.guggenheim.org/login")?;
+        *scraper.fetch_options.cookies.write().unwrap() = HashMap::from([(
+            "members".to_string(),
+            vec![
+                Cookie::parse("sid=abc; Path=/", &url, now_secs(), 60).unwrap(),
+                Cookie::parse("other=1; Path=/other", &url, now_secs(), 60).unwrap(),
+            ],
+        )]);
+        // start_sessions resets the cookies, so fetch the pages directly.
+        let mut span = global::tracer("test").start("test");
+        let pages = Scraper::<FakeSender>::fetch_pages(
+            &scraper.fetch_options,
+            &scraper.targets[0],
+            &mut span,
+        )?;
+        assert_eq!(pages[0].body, "<li>Assistant Curator</li>");
+        assert_eq!(pages[0].url, "https://www.guggenheim.org/careers");
+
+        assert_eq!(
+            Scraper::<FakeSender>::fetch_pages(
+                &scraper.fetch_options,
+                &scraper.targets[1],
+                &mut span
+            ),
+            Err(LmkError::Fetch("renderer: 500".to_string()))
+        );
+
+        scraper.fetch_options.renderer = None;
+        assert_eq!(
+            Scraper::<FakeSender>::fetch_pages(
+                &scraper.fetch_options,
+                &scraper.targets[0],
+                &mut span
+            ),
+            Err(LmkError::Config("no renderer configured".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetcher_redirects() -> Result<(), Box<dyn std::error::Error>> {
+        let fetcher = FakeFetcher::default()
+            .redirect("https://museum.org/jobs", "/careers/jobs")
+            .page(
+                "https://museum.org/careers/jobs",
+                r#"<li>Curator</li><a class="next" href="page2">next</a>"#,
+            )
+            .page(
+                "https://museum.org/careers/page2",
+                "<li>Assistant Curator</li>",
+            );
+        let sender = FakeSender::new();
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            next_page: "a.next".to_string(),
+            ..Default::default()
+        };
+        let scraper = Scraper::with_fetcher(vec![target], &sender, fetcher);
+        scraper.scrape()?;
+        // Links are relative to the page the redirect ended at.
+        assert_eq!(
+            scraper.fetch_options.fetcher.requested_uris(),
+            vec![
+                "https://museum.org/jobs",
+                "https://museum.org/careers/page2"
+            ]
+        );
+        assert_eq!(
+            *sender.msgs.borrow(),
+            vec![
+                "[to default] Target https://museum.org/jobs. msg: \n Found match: Curator",
+                "[to default] Target https://museum.org/jobs. msg: \n Found match: Assistant Curator",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetcher_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let fetcher = FakeFetcher::default()
+            .respond(
+                "https://slow.org/jobs",
+                Err(FetchError::Failed("timeout".to_string())),
+            )
+            .respond(
+                "https://down.org/jobs",
+                Err(FetchError::Failed("connect error".to_string())),
+            )
+            .page(
+                "https://museum.org/jobs",
+                r#"<li>Curator</li><a class="next" href="/jobs?page=2">next</a>"#,
+            )
+            .respond(
+                "https://museum.org/jobs?page=2",
+                Err(FetchError::Failed("timeout".to_string())),
+            );
+        let target = |uri: &str| Target {
+            uri: uri.to_string(),
+            text: "Curator".to_string(),
+            next_page: "a.next".to_string(),
+            ..Default::default()
+        };
+        let mut span = global::tracer("test").start("test");
+        let options = FetchOptions {
+            fetcher,
+            ..Default::default()
+        };
+        assert_eq!(
+            Scraper::<FakeSender, FakeFetcher>::fetch_pages(
+                &options,
+                &target("https://slow.org/jobs"),
+                &mut span
+            ),
+            Err(LmkError::Fetch("timeout".to_string()))
+        );
+        // Failing to fetch a next page only ends the pagination.
+        let pages = Scraper::<FakeSender, FakeFetcher>::fetch_pages(
+            &options,
+            &target("https://museum.org/jobs"),
+            &mut span,
+        )?;
+        assert_eq!(pages.len(), 1);
+
+        // Failing targets don't keep the others from being reported.
+        let sender = FakeSender::new();
+        let scraper = Scraper::with_fetcher(
+            vec![
+                target("https://slow.org/jobs"),
+                target("https://down.org/jobs"),
+                target("https://museum.org/jobs"),
+            ],
+            &sender,
+            options.fetcher,
+        );
+        scraper.scrape()?;
+        assert_eq!(
+            *sender.msgs.borrow(),
+            vec!["[to default] Target https://museum.org/jobs. msg: \n Found match: Curator"]
+        );
+        Ok(())
+    }
+
+    // Runs the targets of targets.yaml against trimmed down copies of their pages, saved in
+    // testdata/<host>.html. Every target needs a page in testdata.
+    #[test]
+    fn test_targets_fixtures() -> Result<(), Box<dyn std::error::Error>> {
+        let expected = std::collections::HashMap::from([
+            (
+                "www.brooklynmuseum.org",
+                vec!["Found match: Assistant Curator, Egyptian, Classical, and Ancient Near Eastern Art"],
+            ),
+            (
+                "whitney.org",
+                vec![
+                    "Found match: Curatorial",
+                    "Found match: Curatorial Fellow",
+                    "Found match: Assistant Curator",
+                ],
+            ),
+            (
+                "www.sculpture-center.org",
+                vec![
+                    "Found match: Curatorial Assistant",
+                    "Found match: SculptureCenter seeks a Curatorial Assistant",
+                ],
+            ),
+            (
+                "www.cooperhewitt.org",
+                vec!["Found match: Associate Curator, Contemporary Design"],
+            ),
+            ("www.newmuseum.org", vec!["Found match: Curator"]),
+            (
+                "www.icp.org",
+                vec![
+                    "Found match: ICP is seeking an Assistant Curator.",
+                    "Found posting: Assistant Curator (New York, NY) https://www.icp.org/jobs/assistant-curator",
+                ],
+            ),
+        ]);
+        let targets = crate::read_config(crate::TARGETS_PATH)?.targets;
+        assert_eq!(targets.len(), expected.len());
+        for mut target in targets {
+            let host = Url::parse(&target.uri)?.host_str().unwrap().to_string();
+            let want = expected
+                .get(host.as_str())
+                .unwrap_or_else(|| panic!("no testdata for {}", target.uri));
+            target.uri = testdata_uri(&format!("{}.html", host));
+            let sender = FakeSender::new();
+            let scraper = Scraper::new_in_memory(vec![target], &sender);
+            scraper.scrape()?;
+            let msgs = sender.msgs.borrow();
+            assert_eq!(msgs.len(), want.len(), "{}: {:?}", host, msgs);
+            for (msg, want) in msgs.iter().zip(want) {
+                assert!(msg.contains(want), "{}: got {} want {}", host, msg, want);
+            }
+            drop(msgs);
+            // Nothing changed, so nothing new to report.
+            scraper.scrape()?;
+            assert_eq!(sender.msgs.borrow().len(), want.len());
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_serialize_deserialize_target() -> Result<(), Box<dyn std::error::Error>> {
         let t = Target {
@@ -0,0 +1,113 @@
+// Delivery of notifications to http APIs, shared by the senders: timeouts, retries of failed
+// posts and pacing of posts to stay under rate limits.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::LmkError;
+
+pub const MAX_ATTEMPTS: u32 = 3;
+// Longest a rate limited post waits for, longer Retry-Afters fail the post.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Sends request. Failures with a 5xx status (or no response at all) are retried after
+// retry_delay (doubled for every other retry), rate limited (429) requests after the Retry-After
+// of the response, up to MAX_ATTEMPTS attempts. backend prefixes the errors.
+pub fn send_with_retries(
+    request: reqwest::blocking::RequestBuilder,
+    backend: &str,
+    retry_delay: Duration,
+) -> Result<(), LmkError> {
+    let mut delay = retry_delay;
+    let mut attempt = 1;
+    loop {
+        let attempt_request = request
+            .try_clone()
+            .ok_or_else(|| LmkError::Notify(format!("{}: request can't be retried", backend)))?;
+        let (status, wait) = match attempt_request.send() {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                let wait = retry_after(&response).unwrap_or(delay);
+                if wait > MAX_RETRY_AFTER {
+                    return Err(LmkError::Notify(format!(
+                        "{}: rate limited for {:?}",
+                        backend, wait
+                    )));
+                }
+                (response.status().to_string(), wait)
+            }
+            Ok(response) if !response.status().is_server_error() => {
+                return Err(LmkError::Notify(format!(
+                    "{}: {}",
+                    backend,
+                    response.status()
+                )));
+            }
+            Ok(response) => (response.status().to_string(), delay),
+            Err(e) => (e.to_string(), delay),
+        };
+        if attempt == MAX_ATTEMPTS {
+            return Err(LmkError::Notify(format!("{}: {}", backend, status)));
+        }
+        log::warn!("{} failed with {}, retrying...", backend, status);
+        std::thread::sleep(wait);
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+// Returns the Retry-After of response, which is in seconds (possibly fractional, e.g., discord).
+fn retry_after(response: &reqwest::blocking::Response) -> Option<Duration> {
+    let secs: f64 = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+// Spaces posts at least min_interval apart, to keep under the rate limit of a platform.
+pub struct Pacer {
+    min_interval: Duration,
+    // Held while waiting, so that concurrent posts are spaced too.
+    last: Mutex<Option<Instant>>,
+}
+
+impl Pacer {
+    pub fn new(min_interval: Duration) -> Self {
+        Pacer {
+            min_interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    // Waits until min_interval passed since the last call.
+    pub fn wait(&self) {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(wait) = last.and_then(|x| self.min_interval.checked_sub(x.elapsed())) {
+            std::thread::sleep(wait);
+        }
+        *last = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer() {
+        let pacer = Pacer::new(Duration::from_millis(50));
+        let start = Instant::now();
+        pacer.wait();
+        assert!(start.elapsed() < Duration::from_millis(50));
+        pacer.wait();
+        pacer.wait();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
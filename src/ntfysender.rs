@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::error::LmkError;
+use crate::myscraper::{Sender, Target};
+use crate::notify::{self, send_with_retries};
+
+// Publishes push notifications to ntfy topics, addr is the url of the topic, e.g.,
+// https://ntfy.sh/museum-jobs. Protected topics need the access token in the NTFY_TOKEN env
+// variable.
+pub struct NtfySender {
+    client: reqwest::blocking::Client,
+    token: Option<String>,
+    retry_delay: Duration,
+}
+
+impl Sender for NtfySender {
+    fn name(&self) -> String {
+        "ntfy".to_string()
+    }
+
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        // Messages are published as JSON to the root of the server, which takes the topic in the
+        // message, e.g., https://ntfy.sh/museum-jobs -> https://ntfy.sh/ and museum-jobs.
+        let (root, topic) = match addr.trim_end_matches('/').rsplit_once('/') {
+            Some((root, topic)) if !topic.is_empty() && root.contains("://") => (root, topic),
+            _ => {
+                return Err(LmkError::Config(format!(
+                    "invalid ntfy topic url {:?}",
+                    addr
+                )))
+            }
+        };
+        let mut request = self
+            .client
+            .post(format!("{}/", root))
+            .json(&payload(topic, target, &msg, link));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        send_with_retries(request, "ntfy", self.retry_delay)
+    }
+}
+
+impl NtfySender {
+    pub fn new() -> Result<Self, LmkError> {
+        Self::with_retry_delay(notify::DEFAULT_RETRY_DELAY)
+    }
+
+    fn with_retry_delay(retry_delay: Duration) -> Result<Self, LmkError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(notify::TIMEOUT)
+            .build()
+            .map_err(|e| LmkError::Config(format!("ntfy: {}", e)))?;
+        Ok(NtfySender {
+            client,
+            token: std::env::var("NTFY_TOKEN").ok(),
+            retry_delay,
+        })
+    }
+}
+
+// Returns the message published to topic, clicking the notification opens the match link.
+fn payload(topic: &str, target: &Target, msg: &str, link: &str) -> Value {
+    let title = if target.text.is_empty() {
+        &target.uri
+    } else {
+        &target.text
+    };
+    json!({
+        "topic": topic,
+        "title": title,
+        "message": msg,
+        "priority": target.priority(),
+        "tags": target.tags,
+        "click": link,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::matchers::{eq, json_decoded, request};
+    use httptest::responders::status_code;
+    use httptest::{all_of, cycle, Expectation};
+
+    #[test]
+    fn test_ntfy_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/"),
+                request::body(json_decoded(eq(json!({
+                    "topic": "museum-jobs",
+                    "title": "Curator",
+                    "message": "Found new page: https://museum.org/jobs/1 (Curator)",
+                    "priority": 4,
+                    "tags": ["art"],
+                    "click": "https://museum.org/jobs/1",
+                })))),
+            ])
+            .times(2)
+            .respond_with(cycle![status_code(502), status_code(200)]),
+        );
+        let sender = NtfySender::with_retry_delay(Duration::ZERO)?;
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            priority: Some(4),
+            tags: vec!["art".to_string()],
+            ..Default::default()
+        };
+        sender.send(
+            &server.url_str("/museum-jobs"),
+            &target,
+            "Found new page: https://museum.org/jobs/1 (Curator)".to_string(),
+            "https://museum.org/jobs/1",
+        )?;
+        assert!(matches!(
+            sender.send("museum-jobs", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Config(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_defaults() {
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            payload("museum-jobs", &target, "Content changed", &target.uri),
+            json!({
+                "topic": "museum-jobs",
+                "title": "https://museum.org/jobs",
+                "message": "Content changed",
+                "priority": 3,
+                "tags": [],
+                "click": "https://museum.org/jobs",
+            })
+        );
+    }
+}
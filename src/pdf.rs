@@ -0,0 +1,185 @@
+// Text extraction for job descriptions that are only posted as PDFs.
+//
+// The documents are parsed with lopdf, the text showing operators of the page content streams are
+// interpreted with strings taken as Latin-1. That covers the PDFs written by word processors with
+// standard fonts, text in embedded (CID) fonts with custom encodings comes out garbled.
+//
+// Content streams are inflated here rather than by lopdf, so that a small PDF can't expand to
+// more than MAX_CONTENT_BYTES. lopdf still inflates the object streams of PDF 1.5+ files itself,
+// those only hold the dictionaries of the objects.
+
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use lopdf::content::Content;
+use lopdf::{Document, Object};
+
+use crate::error::LmkError;
+
+// Max number of bytes of the (decompressed) content streams of a PDF, the rest of the streams are
+// skipped.
+const MAX_CONTENT_BYTES: u64 = 16 << 20;
+
+// Returns the text of pdf, one line per line of text (as far as the content streams tell).
+pub fn extract_text(pdf: &[u8]) -> Result<String, LmkError> {
+    if !pdf.starts_with(b"%PDF-") {
+        return Err(LmkError::Parse("not a pdf".to_string()));
+    }
+    let doc = Document::load_mem(pdf).map_err(|e| LmkError::Parse(format!("pdf: {}", e)))?;
+    let mut budget = MAX_CONTENT_BYTES;
+    let mut lines = vec![];
+    for page_id in doc.page_iter() {
+        for id in doc.get_page_contents(page_id) {
+            let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) else {
+                continue;
+            };
+            let data = match stream.filters().ok().as_deref() {
+                None | Some([]) => stream.content.clone(),
+                Some([filter]) if filter == "FlateDecode" => {
+                    match inflate(&stream.content, budget) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            log::warn!("failed to decompress pdf stream: {}", e);
+                            continue;
+                        }
+                    }
+                }
+                // Other encodings aren't used for text by word processors.
+                Some(filters) => {
+                    log::warn!("skipping pdf stream encoded with {:?}", filters);
+                    continue;
+                }
+            };
+            budget = budget.saturating_sub(data.len() as u64);
+            lines.extend(content_text(&data));
+            if budget == 0 {
+                log::warn!(
+                    "pdf content exceeds {} bytes, skipping the rest",
+                    MAX_CONTENT_BYTES
+                );
+                return Ok(join_lines(&lines));
+            }
+        }
+    }
+    Ok(join_lines(&lines))
+}
+
+fn join_lines(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|x| x.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Inflates the zlib compressed data, failing if it holds more than max_bytes.
+fn inflate(data: &[u8], max_bytes: u64) -> Result<Vec<u8>, String> {
+    let mut inflated = vec![];
+    ZlibDecoder::new(data)
+        .take(max_bytes + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| e.to_string())?;
+    if inflated.len() as u64 > max_bytes {
+        return Err(format!("more than {} bytes", max_bytes));
+    }
+    Ok(inflated)
+}
+
+// Returns the lines of text shown by the content stream data.
+fn content_text(data: &[u8]) -> Vec<String> {
+    let content = match Content::decode(data) {
+        Ok(content) => content,
+        Err(e) => {
+            log::warn!("failed to parse pdf content stream: {}", e);
+            return vec![];
+        }
+    };
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut new_line = |line: &mut String| {
+        if !line.trim().is_empty() {
+            lines.push(std::mem::take(line));
+        }
+        line.clear();
+    };
+    let latin1 = |s: &[u8]| s.iter().map(|&c| c as char).collect::<String>();
+    for op in content.operations {
+        match op.operator.as_str() {
+            "Td" | "TD" | "T*" | "Tm" | "ET" => new_line(&mut line),
+            "Tj" | "'" | "\"" => {
+                if op.operator != "Tj" {
+                    new_line(&mut line);
+                }
+                if let Some(Ok(s)) = op.operands.last().map(Object::as_str) {
+                    line.push_str(&latin1(s));
+                }
+            }
+            "TJ" => {
+                let Some(Ok(items)) = op.operands.last().map(Object::as_array) else {
+                    continue;
+                };
+                for item in items {
+                    match item {
+                        Object::String(s, _) => line.push_str(&latin1(s)),
+                        // Large negative offsets separate words.
+                        Object::Integer(x) if *x < -200 => line.push(' '),
+                        Object::Real(x) if *x < -200.0 => line.push(' '),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    new_line(&mut line);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_inflate() -> Result<(), Box<dyn std::error::Error>> {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&[b' '; 1 << 20])?;
+        let compressed = encoder.finish()?;
+        assert_eq!(inflate(&compressed, 1 << 20)?.len(), 1 << 20);
+        // A small stream can't expand past the limit.
+        assert!(compressed.len() < 2000);
+        assert_eq!(
+            inflate(&compressed, 1000),
+            Err("more than 1000 bytes".to_string())
+        );
+        assert!(inflate(&compressed[..10], 1 << 20).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_text() {
+        let content = br#"BT /F1 12 Tf 72 720 Td (Assistant Curator) Tj
+            0 -14 Td [(Photo)-120(gra)10(phy)-300(Dept.)] TJ
+            (Salary: \(see below\)\0721) ' <48656C6C6F> Tj ET"#;
+        assert_eq!(
+            content_text(content),
+            vec![
+                "Assistant Curator",
+                "Photography Dept.",
+                "Salary: (see below):1Hello"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_text() {
+        let pdf = std::fs::read("testdata/job-description.pdf").unwrap();
+        let text = extract_text(&pdf).unwrap();
+        assert!(text.contains("Assistant Curator of Photography"));
+        assert!(text.contains("Applications close November 30"));
+        assert!(extract_text(b"<html>").is_err());
+    }
+}
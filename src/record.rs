@@ -0,0 +1,92 @@
+// Recording and replaying of fetched responses.
+//
+// With --record every response fetched during a run is stored as json in a run directory, with
+// --replay the responses are read back from such a directory instead of being fetched. This makes
+// it possible to reproduce a run, e.g., to debug why an alert was sent.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fetcher::Response;
+
+// Filenames are limited to 255 bytes on most filesystems, this leaves room for the hash and the
+// extension.
+const MAX_NAME_LEN: usize = 200;
+
+// Returns the file under dir that holds the response of uri. The uri is percent-encoded, so each uri
+// gets its own file, and long uris are cut short and told apart by a hash of the full uri.
+fn response_path(dir: &Path, uri: &str) -> PathBuf {
+    let mut name: String = url::form_urlencoded::byte_serialize(uri.as_bytes()).collect();
+    if name.len() > MAX_NAME_LEN {
+        // The encoding is ascii, so any byte index is a char boundary.
+        name.truncate(MAX_NAME_LEN);
+        name = format!("{}-{:016x}", name, fnv1a(uri.as_bytes()));
+    }
+    dir.join(format!("{}.json", name))
+}
+
+// The 64 bit FNV-1a hash of data, it's stable across runs and builds unlike std's DefaultHasher.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Stores the response that was fetched for uri in dir.
+pub fn save(dir: &Path, uri: &str, response: &Response) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let file = fs::File::create(response_path(dir, uri))?;
+    serde_json::to_writer_pretty(io::BufWriter::new(file), response)?;
+    Ok(())
+}
+
+// Loads the response that was stored for uri in dir.
+pub fn load(dir: &Path, uri: &str) -> io::Result<Response> {
+    let file = fs::File::open(response_path(dir, uri))?;
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("lmk-test-save-load-{}", std::process::id()));
+        let response = Response {
+            url: "https://museum.org/careers/".to_string(),
+            status: 200,
+            headers: vec![("content-type".to_string(), "text/html".to_string())],
+            body: "<li>Curator</li>".to_string(),
+            ..Default::default()
+        };
+        save(&dir, "https://museum.org/careers", &response)?;
+        assert_eq!(load(&dir, "https://museum.org/careers")?, response);
+        assert_eq!(
+            load(&dir, "https://museum.org/other").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_path() {
+        let dir = Path::new("run");
+        // Uris that only differ in punctuation get their own files.
+        assert_ne!(
+            response_path(dir, "https://museum.org/a-b"),
+            response_path(dir, "https://museum.org/a_b")
+        );
+        assert_eq!(
+            response_path(dir, "https://museum.org/jobs?q=1"),
+            dir.join("https%3A%2F%2Fmuseum.org%2Fjobs%3Fq%3D1.json")
+        );
+        let long = format!("https://museum.org/jobs?q={}", "x".repeat(300));
+        let path = response_path(dir, &long);
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.len() < 255);
+        assert_ne!(path, response_path(dir, &format!("{}y", long)));
+    }
+}
@@ -0,0 +1,378 @@
+// Login sessions for targets behind a login form, e.g., member only or intranet job boards.
+//
+// A session logs in by posting a form (credentials come from environment variables) and keeps
+// the cookies that were set. The cookies are persisted in the target cache and reused across runs
+// until they expire.
+
+use std::collections::BTreeMap;
+
+use chrono::DateTime;
+use opentelemetry::trace::Span;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::LmkError;
+use crate::fetcher::{Fetcher, Method, Request};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct Session {
+    // The uri the login form is posted to.
+    pub login_uri: String,
+    // The fields of the login form. "${VAR}" in values is replaced by the environment variable
+    // VAR, e.g., password: ${MUSEUM_PASSWORD}.
+    #[serde(default)]
+    pub form: BTreeMap<String, String>,
+    // How long cookies without an expiry are kept, defaults to DEFAULT_TTL_SECS.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+impl Session {
+    const DEFAULT_TTL_SECS: u64 = 60 * 60;
+
+    // Returns the form fields with the environment variables substituted.
+    pub fn form_values(&self) -> Result<Vec<(String, String)>, LmkError> {
+        self.form
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), substitute_env(value)?)))
+            .collect()
+    }
+
+    // Posts the login form with fetcher, returning the cookies that were set.
+    pub fn login(
+        &self,
+        fetcher: &impl Fetcher,
+        now: u64,
+        span: &mut impl Span,
+    ) -> Result<Vec<Cookie>, LmkError> {
+        let url = Url::parse(&self.login_uri)
+            .map_err(|e| LmkError::Config(format!("invalid login_uri: {}", e)))?;
+        let form = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(self.form_values()?)
+            .finish();
+        // Logins usually redirect, the cookies are set on the redirect response.
+        let request = Request {
+            method: Method::Post,
+            uri: url.to_string(),
+            headers: vec![(
+                "content-type".to_string(),
+                "application/x-www-form-urlencoded".to_string(),
+            )],
+            body: Some(form),
+            no_redirects: true,
+            ..Default::default()
+        };
+        let response = fetcher.fetch(&request, span)?;
+        if !(200..400).contains(&response.status) {
+            return Err(LmkError::Fetch(format!(
+                "login failed: {}",
+                response.status
+            )));
+        }
+        let ttl = self.ttl_secs.unwrap_or(Self::DEFAULT_TTL_SECS);
+        let cookies: Vec<_> = response
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("set-cookie"))
+            .filter_map(|(_, x)| Cookie::parse(x, &url, now, ttl))
+            .collect();
+        if cookies.is_empty() {
+            return Err(LmkError::Fetch("login set no cookies".to_string()));
+        }
+        Ok(cookies)
+    }
+}
+
+// Replaces the "${VAR}"s in value by the environment variables.
+fn substitute_env(value: &str) -> Result<String, LmkError> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| LmkError::Config(format!("unterminated variable in {:?}", value)))?;
+        let name = &rest[start + 2..start + end];
+        let var =
+            std::env::var(name).map_err(|_| LmkError::Config(format!("${} isn't set", name)))?;
+        result.push_str(&rest[..start]);
+        result.push_str(&var);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    // The host the cookie is sent to, including its subdomains if it was set with a Domain.
+    pub domain: String,
+    pub include_subdomains: bool,
+    pub path: String,
+    // Seconds since unix epoch.
+    pub expires: u64,
+    // Whether the cookie is only sent over https.
+    #[serde(default)]
+    pub secure: bool,
+}
+
+impl Cookie {
+    // Parses the Set-Cookie header value set by a response of url. Cookies without an expiry
+    // expire after ttl seconds. Cookies for a Domain that the host of url doesn't belong to are
+    // rejected.
+    pub fn parse(header: &str, url: &Url, now: u64, ttl: u64) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let mut cookie = Cookie {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+            domain: url.host_str()?.to_string(),
+            include_subdomains: false,
+            path: "/".to_string(),
+            expires: now + ttl,
+            secure: false,
+        };
+        let mut max_age = None;
+        for part in parts {
+            let (key, value) = part.split_once('=').unwrap_or((part, ""));
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_lowercase();
+                    let host = url.host_str()?;
+                    if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                        log::warn!(
+                            "rejecting cookie {} for domain {} set by {}",
+                            name,
+                            domain,
+                            host
+                        );
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.include_subdomains = true;
+                }
+                "secure" => cookie.secure = true,
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => {
+                    // Some servers use "21-Oct-2022" rather than "21 Oct 2022".
+                    if let Ok(x) = DateTime::parse_from_rfc2822(&value.replace('-', " ")) {
+                        cookie.expires = x.timestamp().max(0) as u64;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Max-Age takes precedence over Expires.
+        if let Some(max_age) = max_age {
+            cookie.expires = now.saturating_add_signed(max_age);
+        }
+        if cookie.name.is_empty() {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires <= now
+    }
+
+    // Returns whether the cookie is sent with requests to url.
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        if self.secure && url.scheme() != "https" {
+            return false;
+        }
+        let domain_matches = host == self.domain
+            || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+        let path = url.path();
+        let path_matches = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain_matches && path_matches
+    }
+}
+
+// Returns the Cookie header of a request to uri, None if no cookie applies.
+pub fn cookie_header(cookies: &[Cookie], uri: &str, now: u64) -> Option<String> {
+    let url = Url::parse(uri).ok()?;
+    let header = cookies
+        .iter()
+        .filter(|x| !x.is_expired(now) && x.matches(&url))
+        .map(|x| format!("{}={}", x.name, x.value))
+        .collect::<Vec<_>>()
+        .join("; ");
+    if header.is_empty() {
+        None
+    } else {
+        Some(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::{FakeFetcher, Response};
+    use opentelemetry::global;
+    use opentelemetry::trace::Tracer;
+
+    #[test]
+    fn test_substitute_env() {
+        std::env::set_var("LMK_TEST_SESSION_USER", "curator");
+        assert_eq!(
+            substitute_env("${LMK_TEST_SESSION_USER}@museum.org"),
+            Ok("curator@museum.org".to_string())
+        );
+        assert_eq!(substitute_env("plain"), Ok("plain".to_string()));
+        assert!(substitute_env("${LMK_TEST_SESSION_UNSET}").is_err());
+        assert!(substitute_env("${LMK_TEST_SESSION_USER").is_err());
+    }
+
+    #[test]
+    fn test_login() {
+        std::env::set_var("LMK_TEST_SESSION_PASSWORD", "s3cret");
+        let session = Session {
+            login_uri: "https://museum.org/login".to_string(),
+            form: BTreeMap::from([
+                ("user".to_string(), "curator".to_string()),
+                (
+                    "password".to_string(),
+                    "${LMK_TEST_SESSION_PASSWORD}".to_string(),
+                ),
+            ]),
+            ttl_secs: None,
+        };
+        let fetcher = FakeFetcher::default()
+            .respond(
+                "https://museum.org/login",
+                Ok(Response {
+                    status: 302,
+                    headers: vec![
+                        ("location".to_string(), "/members".to_string()),
+                        ("set-cookie".to_string(), "sid=abc".to_string()),
+                        ("Set-Cookie".to_string(), "lang=en; Max-Age=60".to_string()),
+                    ],
+                    ..Default::default()
+                }),
+            )
+            .respond(
+                "https://other.org/login",
+                Ok(Response {
+                    status: 401,
+                    ..Default::default()
+                }),
+            );
+        let mut span = global::tracer("test").start("test");
+        let now = 1_666_000_000;
+        let cookies = session.login(&fetcher, now, &mut span).unwrap();
+        assert_eq!(
+            cookies.iter().map(|x| &x.name).collect::<Vec<_>>(),
+            vec!["sid", "lang"]
+        );
+        assert_eq!(cookies[1].expires, now + 60);
+        // The redirect isn't followed, the form is posted url encoded.
+        let requests = fetcher.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].no_redirects);
+        assert_eq!(requests[0].method, Method::Post);
+        assert_eq!(
+            requests[0].body.as_deref(),
+            Some("password=s3cret&user=curator")
+        );
+
+        let session = Session {
+            login_uri: "https://other.org/login".to_string(),
+            ..session
+        };
+        assert_eq!(
+            session.login(&fetcher, now, &mut span),
+            Err(LmkError::Fetch("login failed: 401".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_cookie() {
+        let url = Url::parse("https://jobs.museum.org/login").unwrap();
+        let now = 1_666_000_000;
+        assert_eq!(
+            Cookie::parse("sid=abc; Path=/members; HttpOnly", &url, now, 60),
+            Some(Cookie {
+                name: "sid".to_string(),
+                value: "abc".to_string(),
+                domain: "jobs.museum.org".to_string(),
+                include_subdomains: false,
+                path: "/members".to_string(),
+                expires: now + 60,
+                secure: false,
+            })
+        );
+        let cookie = Cookie::parse(
+            "sid=abc; Domain=.museum.org; Expires=Wed, 21-Oct-2037 07:28:00 GMT",
+            &url,
+            now,
+            60,
+        )
+        .unwrap();
+        assert_eq!(cookie.domain, "museum.org");
+        assert!(cookie.include_subdomains);
+        assert_eq!(cookie.expires, 2_139_722_880);
+        let cookie = Cookie::parse(
+            "sid=abc; Max-Age=10; Expires=Wed, 21 Oct 2037 07:28:00 GMT",
+            &url,
+            now,
+            60,
+        );
+        assert_eq!(cookie.unwrap().expires, now + 10);
+        assert_eq!(Cookie::parse("garbage", &url, now, 60), None);
+        // Hosts can't set cookies for other sites, or for their own subdomains.
+        assert_eq!(
+            Cookie::parse("sid=abc; Domain=other.org", &url, now, 60),
+            None
+        );
+        assert_eq!(
+            Cookie::parse("sid=abc; Domain=seum.org", &url, now, 60),
+            None
+        );
+        assert_eq!(
+            Cookie::parse("sid=abc; Domain=www.jobs.museum.org", &url, now, 60),
+            None
+        );
+        assert!(
+            Cookie::parse("sid=abc; Secure", &url, now, 60)
+                .unwrap()
+                .secure
+        );
+    }
+
+    #[test]
+    fn test_cookie_header() {
+        let url = Url::parse("https://jobs.museum.org/login").unwrap();
+        let cookies = vec![
+            Cookie::parse("a=1; Path=/members", &url, 100, 60).unwrap(),
+            Cookie::parse("b=2; Domain=museum.org", &url, 100, 60).unwrap(),
+            Cookie::parse("c=3", &url, 100, 10).unwrap(),
+            Cookie::parse("d=4; Secure", &url, 100, 60).unwrap(),
+        ];
+        assert_eq!(
+            cookie_header(&cookies, "https://jobs.museum.org/members/jobs", 105),
+            Some("a=1; b=2; c=3; d=4".to_string())
+        );
+        assert_eq!(
+            cookie_header(&cookies, "https://jobs.museum.org/membership", 105),
+            Some("b=2; c=3; d=4".to_string())
+        );
+        assert_eq!(
+            cookie_header(&cookies, "http://jobs.museum.org/membership", 105),
+            Some("b=2; c=3".to_string())
+        );
+        assert_eq!(
+            cookie_header(&cookies, "https://www.museum.org/", 120),
+            Some("b=2".to_string())
+        );
+        assert_eq!(cookie_header(&cookies, "https://other.org/", 105), None);
+    }
+}
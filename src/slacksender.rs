@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use crate::error::LmkError;
+use crate::formatting::{self, truncate};
+use crate::myscraper::{Sender, Target};
+use crate::notify::{self, send_with_retries, Pacer};
+
+// Posts Block Kit messages to a Slack incoming webhook, addr is the webhook url, e.g.,
+// https://hooks.slack.com/services/T000/B000/XXXX.
+pub struct SlackSender {
+    client: reqwest::blocking::Client,
+    pacer: Pacer,
+    retry_delay: Duration,
+}
+
+impl Sender for SlackSender {
+    fn name(&self) -> String {
+        "slack".to_string()
+    }
+
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        self.pacer.wait();
+        let payload = payload(target, &msg, link, Utc::now());
+        let request = self.client.post(addr).json(&payload);
+        send_with_retries(request, "slack", self.retry_delay)
+    }
+}
+
+impl SlackSender {
+    // Incoming webhooks take about a message per second.
+    const MIN_INTERVAL: Duration = Duration::from_secs(1);
+    // Max length of the text of a header block.
+    const MAX_HEADER_CHARS: usize = 150;
+    // Max length of the text of a section block.
+    const MAX_SECTION_CHARS: usize = 3000;
+
+    pub fn new() -> Result<Self, LmkError> {
+        Self::with_limits(Self::MIN_INTERVAL, notify::DEFAULT_RETRY_DELAY)
+    }
+
+    fn with_limits(min_interval: Duration, retry_delay: Duration) -> Result<Self, LmkError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(notify::TIMEOUT)
+            .build()
+            .map_err(|e| LmkError::Config(format!("slack: {}", e)))?;
+        Ok(SlackSender {
+            client,
+            pacer: Pacer::new(min_interval),
+            retry_delay,
+        })
+    }
+}
+
+// Returns the Block Kit message of the alert msg about target, found at now: a header with the
+// text looked for, the alert with link (the link to the match) and the time it was found.
+fn payload(target: &Target, msg: &str, link: &str, now: DateTime<Utc>) -> Value {
+    let title = if target.text.is_empty() {
+        &target.uri
+    } else {
+        &target.text
+    };
+    let section = format!("<{}|{}>\n{}", link, escape(link), escape(msg));
+    let found = format!(
+        "Found <!date^{}^{{date_short_pretty}} at {{time}}|{}>",
+        now.timestamp(),
+        now.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    json!({
+        // Shown in notifications.
+        "text": truncate(&formatting::text(target, msg), SlackSender::MAX_SECTION_CHARS),
+        "blocks": [
+            {
+                "type": "header",
+                "text": {
+                    "type": "plain_text",
+                    "text": truncate(title, SlackSender::MAX_HEADER_CHARS),
+                },
+            },
+            {
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": truncate(&section, SlackSender::MAX_SECTION_CHARS),
+                },
+            },
+            {
+                "type": "context",
+                "elements": [{"type": "mrkdwn", "text": found}],
+            },
+        ],
+    })
+}
+
+// Escapes the control characters of mrkdwn.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::matchers::{json_decoded, request};
+    use httptest::responders::status_code;
+    use httptest::{all_of, cycle, Expectation};
+
+    fn target() -> Target {
+        Target {
+            uri: "https://museum.org/jobs?a=1&b=2".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_payload() {
+        let now = DateTime::parse_from_rfc3339("2022-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let message = payload(
+            &target(),
+            "Found match: <Curator>",
+            "https://museum.org/jobs/1?a=1&b=2",
+            now,
+        );
+        assert_eq!(message["blocks"][0]["text"]["text"], json!("Curator"));
+        assert_eq!(
+            message["blocks"][1]["text"]["text"],
+            json!(
+                "<https://museum.org/jobs/1?a=1&b=2|https://museum.org/jobs/1?a=1&amp;b=2>\nFound match: &lt;Curator&gt;"
+            )
+        );
+        assert_eq!(
+            message["blocks"][2]["elements"][0]["text"],
+            json!("Found <!date^1666337280^{date_short_pretty} at {time}|2022-10-21T07:28:00Z>")
+        );
+
+        // Long alerts, e.g., diffs, are cut at the section limit.
+        let message = payload(&target(), &"+ Curator\n".repeat(1000), "", now);
+        let section = message["blocks"][1]["text"]["text"].as_str().unwrap();
+        assert_eq!(section.chars().count(), SlackSender::MAX_SECTION_CHARS);
+        assert!(section.ends_with('…'));
+    }
+
+    #[test]
+    fn test_slack_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/services/T000/B000/XXXX"),
+                request::body(json_decoded(|x: &Value| {
+                    x["text"] == json!("https://museum.org/jobs?a=1&b=2: Found match: Curator")
+                })),
+            ])
+            .times(2)
+            .respond_with(cycle![
+                status_code(429).insert_header("Retry-After", "0"),
+                status_code(200).body("ok"),
+            ]),
+        );
+        let sender = SlackSender::with_limits(Duration::ZERO, Duration::ZERO)?;
+        sender.send(
+            &server.url_str("/services/T000/B000/XXXX"),
+            &target(),
+            "Found match: Curator".to_string(),
+            &target().uri,
+        )?;
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/bad"))
+                .respond_with(status_code(400).body("invalid_blocks")),
+        );
+        assert_eq!(
+            sender.send(
+                &server.url_str("/bad"),
+                &target(),
+                "x".to_string(),
+                &target().uri
+            ),
+            Err(LmkError::Notify("slack: 400 Bad Request".to_string()))
+        );
+        Ok(())
+    }
+}
@@ -1,42 +1,512 @@
+use clap::ValueEnum;
+use std::time::Duration;
+
 use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::{ApiError, RequestError};
 use tokio::runtime::Runtime;
 
+use crate::error::LmkError;
+use crate::formatting::{self, escape_html};
 use crate::myscraper::{Sender, Target};
+use crate::notify::{self, MAX_ATTEMPTS, MAX_RETRY_AFTER};
+
+// Max number of characters of a telegram message, longer messages are split.
+const MAX_MESSAGE_CHARS: usize = 4096;
+
+// How messages are formatted, links in them are clickable either way.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum TelegramFormat {
+    /// Telegram HTML
+    #[default]
+    Html,
+    /// Telegram MarkdownV2
+    MarkdownV2,
+}
 
 // A Teloxide telegram bot sender. Requires that env variable of TELOXIDE_TOKEN
 // being set e.g, $ export TELOXIDE_TOKEN=<Your token here>
 pub struct TelegramSender {
-    // Telegram chat id that all messages are sent to, provided in `new` method.
-    chat_id: ChatId,
     // A teloxide bot. Requires bot token being in environment.
     // $ export TELOXIDE_TOKEN=<Your token here>
     bot: Bot,
     // Used to wait on the futures returned by bot.send_message.
     rt: Runtime,
+    format: TelegramFormat,
+    // Whether telegram shows previews of the links in the messages.
+    disable_link_preview: bool,
+    // Delay of the first retry of a failed message, doubled for every other retry.
+    retry_delay: Duration,
 }
 
 impl Sender for TelegramSender {
-    fn send(&self, addr: &str, target: &Target, msg: String) {
+    fn name(&self) -> String {
+        "telegram".to_string()
+    }
+
+    // addr is the chat id, e.g., -727046961.
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
         eprintln!("[to {}] Target {}. msg: \n {}", addr, target.uri, msg);
-        if let Err(e) = self.rt.block_on(
-            self.bot
-                .send_message(self.chat_id, format!("{}: {}", target.uri, msg))
-                .send(),
-        ) {
-            eprintln!("failed to send for target {:?}, err: {} ", target, e);
+        let chat_id = addr
+            .parse()
+            .map(ChatId)
+            .map_err(|_| LmkError::Config(format!("invalid telegram chat id {:?}", addr)))?;
+        // Like the subject of emails, the uri of the target comes with every untemplated message.
+        let text = formatting::text(target, &msg);
+        let parse_mode = match self.format {
+            TelegramFormat::Html => ParseMode::Html,
+            TelegramFormat::MarkdownV2 => ParseMode::MarkdownV2,
+        };
+        let parts = split(&text, MAX_MESSAGE_CHARS);
+        for (i, part) in parts.iter().enumerate() {
+            let text = format(part, self.format, &[&target.uri, link]);
+            self.send_with_retries(chat_id, &text, parse_mode)
+                .map_err(|e| match e {
+                    _ if i == 0 => e,
+                    LmkError::Notify(x) => LmkError::Notify(partly_sent(x, i, parts.len())),
+                    LmkError::Undeliverable(x) => {
+                        LmkError::Undeliverable(partly_sent(x, i, parts.len()))
+                    }
+                    e => e,
+                })?;
         }
+        Ok(())
     }
 }
 
 impl TelegramSender {
-    // Creates a new Sender, chat_id is a telegram chat id, e.g., -727046961
-    pub fn new(chat_id: i64) -> Result<Self, Box<dyn std::error::Error>> {
-        let bot = Bot::from_env();
+    // Creates a new Sender, the chats messages are sent to are the addresses of the notification
+    // channels.
+    pub fn new(format: TelegramFormat, disable_link_preview: bool) -> Result<Self, LmkError> {
+        let token = std::env::var("TELOXIDE_TOKEN")
+            .map_err(|_| LmkError::Config("TELOXIDE_TOKEN isn't set".to_string()))?;
+        Self::with_bot(
+            Bot::new(token),
+            format,
+            disable_link_preview,
+            notify::DEFAULT_RETRY_DELAY,
+        )
+    }
+
+    fn with_bot(
+        bot: Bot,
+        format: TelegramFormat,
+        disable_link_preview: bool,
+        retry_delay: Duration,
+    ) -> Result<Self, LmkError> {
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
+            .build()
+            .map_err(|e| LmkError::Notify(format!("failed to start the runtime: {}", e)))?;
+
+        Ok(TelegramSender {
+            bot,
+            rt,
+            format,
+            disable_link_preview,
+            retry_delay,
+        })
+    }
+
+    // Sends text to chat_id. Flood controlled messages are retried after the wait telegram asks
+    // for, network failures after retry_delay (doubled for every other retry), up to MAX_ATTEMPTS
+    // attempts. Chats that can't be posted to fail with LmkError::Undeliverable.
+    fn send_with_retries(
+        &self,
+        chat_id: ChatId,
+        text: &str,
+        parse_mode: ParseMode,
+    ) -> Result<(), LmkError> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+        loop {
+            let result = self.rt.block_on(
+                self.bot
+                    .send_message(chat_id, text)
+                    .parse_mode(parse_mode)
+                    .disable_web_page_preview(self.disable_link_preview)
+                    .send(),
+            );
+            let (failure, wait) = match result {
+                Ok(_) => return Ok(()),
+                Err(RequestError::RetryAfter(wait)) if wait > MAX_RETRY_AFTER => {
+                    return Err(LmkError::Notify(format!(
+                        "telegram: rate limited for {:?}",
+                        wait
+                    )));
+                }
+                Err(e @ RequestError::RetryAfter(wait)) => (e.to_string(), wait),
+                // Telegram answers with an html page (rather than json) when it's overloaded,
+                // teloxide already waits 10s before returning 5xx failures.
+                Err(
+                    e @ (RequestError::Network(_)
+                    | RequestError::Io(_)
+                    | RequestError::InvalidJson { .. }),
+                ) => (e.to_string(), delay),
+                Err(RequestError::MigrateToChatId(id)) => {
+                    return Err(LmkError::Undeliverable(format!(
+                        "telegram: chat {} moved to {}",
+                        chat_id, id
+                    )));
+                }
+                Err(RequestError::Api(e)) if is_permanent(&e) => {
+                    return Err(LmkError::Undeliverable(format!(
+                        "telegram: chat {}: {}",
+                        chat_id, e
+                    )));
+                }
+                Err(RequestError::Api(e)) if is_server_error(&e) => (e.to_string(), delay),
+                Err(e) => return Err(LmkError::Notify(format!("telegram: {}", e))),
+            };
+            if attempt == MAX_ATTEMPTS {
+                return Err(LmkError::Notify(format!("telegram: {}", failure)));
+            }
+            log::warn!("telegram failed with {}, retrying...", failure);
+            std::thread::sleep(wait);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+// Returns whether e means that the bot can't post to the chat, until someone changes the chat or
+// the config.
+fn is_permanent(e: &ApiError) -> bool {
+    // Telegram words its errors differently over time, teloxide doesn't know all of them, e.g.,
+    // "Forbidden: bot was kicked from the group chat", but 403s are always about the chat.
+    if let ApiError::Unknown(description) = e {
+        return description.starts_with("Forbidden:");
+    }
+    matches!(
+        e,
+        ApiError::ChatNotFound
+            | ApiError::BotBlocked
+            | ApiError::BotKicked
+            | ApiError::BotKickedFromSupergroup
+            | ApiError::UserDeactivated
+            | ApiError::GroupDeactivated
+            | ApiError::NotEnoughRightsToPostMessages
+            | ApiError::CantInitiateConversation
+            | ApiError::CantTalkWithBots
+    )
+}
+
+// Returns whether e is a failure of telegram's servers (a 5xx), which usually passes. teloxide
+// drops the status code of failures, so they're told apart by their description.
+fn is_server_error(e: &ApiError) -> bool {
+    let ApiError::Unknown(description) = e else {
+        return false;
+    };
+    [
+        "Internal Server Error",
+        "Bad Gateway",
+        "Service Unavailable",
+        "Gateway Timeout",
+    ]
+    .iter()
+    .any(|x| description.starts_with(x))
+}
+
+// Returns the error of the part after the first sent parts of a message of num_parts parts.
+fn partly_sent(e: String, sent: usize, num_parts: usize) -> String {
+    format!(
+        "{} (after sending {} of the {} parts of the message)",
+        e, sent, num_parts
+    )
+}
+
+// Returns text escaped for format, with the occurrences of links (e.g., the uri of the target and
+// the link to the match) turned into links. Longer links take precedence, and a link that is
+// followed by more of a url (e.g., the uri of the target in the url of one of its pages) isn't one.
+fn format(text: &str, format: TelegramFormat, links: &[&str]) -> String {
+    let escape = |s: &str| match format {
+        TelegramFormat::Html => escape_html(s),
+        TelegramFormat::MarkdownV2 => escape_markdown(s),
+    };
+    let link = |url: &str| match format {
+        TelegramFormat::Html => {
+            format!("<a href=\"{}\">{}</a>", escape_html(url), escape_html(url))
+        }
+        // Only ) and \ are special in the urls of MarkdownV2 links.
+        TelegramFormat::MarkdownV2 => format!(
+            "[{}]({})",
+            escape_markdown(url),
+            url.replace('\\', r"\\").replace(')', r"\)")
+        ),
+    };
+    let mut links: Vec<_> = links.iter().filter(|x| !x.is_empty()).collect();
+    links.sort_by_key(|x| std::cmp::Reverse(x.len()));
+    let mut formatted = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let url = links.iter().find(|url| {
+            text[i..].starts_with(**url)
+                && !text[i + url.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '/')
+        });
+        match url {
+            Some(url) => {
+                formatted.push_str(&escape(&text[last..i]));
+                formatted.push_str(&link(url));
+                i += url.len();
+                last = i;
+            }
+            None => i += text[i..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    formatted.push_str(&escape(&text[last..]));
+    formatted
+}
+
+// Escapes the characters that are special in telegram MarkdownV2, see
+// https://core.telegram.org/bots/api#markdownv2-style.
+fn escape_markdown(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\_*[]()~`>#+-=|{}.!".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Splits text into messages of at most max_chars characters, at line breaks if possible, otherwise
+// at spaces.
+fn split(text: &str, max_chars: usize) -> Vec<String> {
+    let mut parts = vec![];
+    let mut rest = text.trim();
+    while rest.chars().count() > max_chars {
+        let (end, _) = rest.char_indices().nth(max_chars).expect("rest is longer");
+        let head = &rest[..end];
+        let cut = head
+            .rfind('\n')
+            .or_else(|| head.rfind(char::is_whitespace))
+            .filter(|x| *x > 0)
+            .unwrap_or(end);
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::matchers::{json_decoded, request};
+    use httptest::responders::{json_encoded, status_code};
+    use httptest::{all_of, cycle, Expectation};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    // Returns a bot that talks to server, without keeping connections alive across the tests.
+    fn test_bot(server: &httptest::Server) -> Result<Bot, Box<dyn std::error::Error>> {
+        let client = reqwest::Client::builder()
+            .pool_max_idle_per_host(0)
             .build()?;
-        let chat_id = ChatId(chat_id);
+        Ok(Bot::with_client("token", client).set_api_url(server.url("/").to_string().parse()?))
+    }
+
+    #[test]
+    fn test_format() {
+        let text = "Found match in https://museum.org/jobs/a_b.pdf: <Curator> & (Registrar)!";
+        let links = ["https://museum.org/jobs", "https://museum.org/jobs/a_b.pdf"];
+        assert_eq!(
+            format(text, TelegramFormat::Html, &links),
+            "Found match in <a href=\"https://museum.org/jobs/a_b.pdf\">https://museum.org/jobs/a_b.pdf</a>: &lt;Curator&gt; &amp; (Registrar)!"
+        );
+        assert_eq!(
+            format(text, TelegramFormat::MarkdownV2, &links),
+            r"Found match in [https://museum\.org/jobs/a\_b\.pdf](https://museum.org/jobs/a_b.pdf): <Curator\> & \(Registrar\)\!"
+        );
+        assert_eq!(
+            format(
+                r"C:\jobs (https://museum.org/a)",
+                TelegramFormat::MarkdownV2,
+                &["https://museum.org/a"]
+            ),
+            r"C:\\jobs \([https://museum\.org/a](https://museum.org/a)\)"
+        );
+        // Only the given links are links, not other urls or the urls they're a part of.
+        assert_eq!(
+            format(
+                "https://museum.org/jobs: https://museum.org/jobs/2 https://museum.org/about",
+                TelegramFormat::Html,
+                &["https://museum.org/jobs", ""]
+            ),
+            "<a href=\"https://museum.org/jobs\">https://museum.org/jobs</a>: https://museum.org/jobs/2 https://museum.org/about"
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("", 10), Vec::<String>::new());
+        assert_eq!(split("Curator", 10), vec!["Curator"]);
+        assert_eq!(
+            split("Assistant Curator\nRegistrar", 20),
+            vec!["Assistant Curator", "Registrar"]
+        );
+        assert_eq!(
+            split("Assistant Curator Registrar", 20),
+            vec!["Assistant Curator", "Registrar"]
+        );
+        assert_eq!(split("ééééé", 2), vec!["éé", "éé", "é"]);
+        let long = "Curator\n".repeat(1000);
+        let parts = split(&long, MAX_MESSAGE_CHARS);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|x| x.chars().count() <= MAX_MESSAGE_CHARS));
+        assert_eq!(parts.join("\n"), long.trim());
+    }
+
+    #[test]
+    fn test_telegram_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let server = httptest::Server::run();
+        let message = json!({
+            "ok": true,
+            "result": {
+                "message_id": 1,
+                "date": 0,
+                "chat": {"id": -100123, "type": "group", "title": "jobs"},
+                "text": "Curator",
+            },
+        });
+        let texts = Arc::new(Mutex::new(vec![]));
+        let recorded = texts.clone();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/bottoken/SendMessage"),
+                request::body(json_decoded(move |x: &serde_json::Value| {
+                    recorded.lock().unwrap().push(x["text"].clone());
+                    x["chat_id"] == -100123
+                        && x["parse_mode"] == "HTML"
+                        && x["disable_web_page_preview"] == true
+                })),
+            ])
+            .times(2)
+            .respond_with(json_encoded(message)),
+        );
+        let bot = test_bot(&server)?;
+        let sender = TelegramSender::with_bot(bot, TelegramFormat::Html, true, Duration::ZERO)?;
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+        let msg = format!("Content changed:\n{}", "+ Curator\n".repeat(500));
+        sender.send("-100123", &target, msg, &target.uri)?;
+        let texts = texts.lock().unwrap();
+        assert!(texts[0]
+            .as_str()
+            .is_some_and(|x| x.starts_with("<a href=\"https://museum.org/jobs\">")));
+        assert_eq!(texts[1], "+ Curator\n".repeat(95).trim());
+        assert!(matches!(
+            sender.send("jobs", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Config(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_telegram_sender_retries() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = httptest::Server::run();
+        let sent = json!({
+            "ok": true,
+            "result": {
+                "message_id": 1,
+                "date": 0,
+                "chat": {"id": -100123, "type": "group", "title": "jobs"},
+                "text": "Curator",
+            },
+        });
+        let error = |code: u16, description: &str| {
+            status_code(code).body(
+                json!({"ok": false, "error_code": code, "description": description}).to_string(),
+            )
+        };
+        let bot = test_bot(&server)?;
+        let sender = TelegramSender::with_bot(bot, TelegramFormat::Html, false, Duration::ZERO)?;
+        let target = Target {
+            uri: "https://museum.org/jobs".to_string(),
+            ..Default::default()
+        };
+
+        // Flood control, server errors and non-json answers (e.g., of a proxy) are retried. The
+        // server error comes with a 200 as teloxide waits 10s on 5xx before parsing them.
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/bottoken/SendMessage"))
+                .times(3)
+                .respond_with(cycle![
+                    status_code(429).body(
+                        json!({
+                            "ok": false,
+                            "error_code": 429,
+                            "description": "Too Many Requests: retry after 0",
+                            "parameters": {"retry_after": 0},
+                        })
+                        .to_string()
+                    ),
+                    error(200, "Internal Server Error"),
+                    json_encoded(sent.clone()),
+                ]),
+        );
+        sender.send(
+            "-100123",
+            &target,
+            "Found match: Curator".to_string(),
+            &target.uri,
+        )?;
+        server.verify_and_clear();
+
+        // Up to MAX_ATTEMPTS attempts.
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/bottoken/SendMessage"))
+                .times(3)
+                .respond_with(status_code(200).body("<html>Bad Gateway</html>")),
+        );
+        assert!(matches!(
+            sender.send("-100123", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Notify(e)) if e.starts_with("telegram: An error while parsing JSON")
+        ));
+        server.verify_and_clear();
+
+        // Chats the bot can't post to aren't retried.
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/bottoken/SendMessage"))
+                .times(2)
+                .respond_with(cycle![
+                    error(400, "Bad Request: chat not found"),
+                    error(403, "Forbidden: bot was kicked from the group chat"),
+                ]),
+        );
+        assert_eq!(
+            sender.send("-100123", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Undeliverable(
+                "telegram: chat -100123: Bad Request: chat not found".to_string()
+            ))
+        );
+        assert!(matches!(
+            sender.send("-100123", &target, "x".to_string(), &target.uri),
+            Err(LmkError::Undeliverable(_))
+        ));
+        server.verify_and_clear();
 
-        Ok(TelegramSender { chat_id, bot, rt })
+        // Failures of later parts tell that the earlier ones were sent.
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/bottoken/SendMessage"))
+                .times(2)
+                .respond_with(cycle![
+                    json_encoded(sent),
+                    error(403, "Forbidden: bot was blocked by the user"),
+                ]),
+        );
+        assert_eq!(
+            sender.send("-100123", &target, "Curator\n".repeat(1000), &target.uri),
+            Err(LmkError::Undeliverable(
+                "telegram: chat -100123: Forbidden: bot was blocked by the user (after sending 1 of the 2 parts of the message)".to_string()
+            ))
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,214 @@
+// Templates of the notification messages, e.g.,
+//   "{description}: {match} (first seen {first_seen})\n{link}"
+// "{field}"s are replaced by the fields of the match, "{{" and "}}" are literal braces. The
+// fields are:
+//   description: description of the target.
+//   uri: uri of the target.
+//   text: the text the target looks for.
+//   match: the text that matched, e.g., the posting or the line of the page.
+//   link: link to the match, e.g., the posting, or the uri of the target.
+//   first_seen: when the match was first seen, e.g., 2022-10-21T07:28:00Z.
+//   message: the default message, e.g., "Found match: Curator".
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::LmkError;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Field {
+    Description,
+    Uri,
+    Text,
+    Match,
+    Link,
+    FirstSeen,
+    Message,
+}
+
+impl Field {
+    const NAMES: [(&'static str, Field); 7] = [
+        ("description", Field::Description),
+        ("uri", Field::Uri),
+        ("text", Field::Text),
+        ("match", Field::Match),
+        ("link", Field::Link),
+        ("first_seen", Field::FirstSeen),
+        ("message", Field::Message),
+    ];
+
+    fn parse(name: &str) -> Option<Field> {
+        Self::NAMES
+            .iter()
+            .find(|(x, _)| *x == name)
+            .map(|(_, field)| *field)
+    }
+}
+
+// The values of the fields a template is rendered with.
+#[derive(Default)]
+pub struct Fields<'a> {
+    pub description: &'a str,
+    pub uri: &'a str,
+    pub text: &'a str,
+    pub m: &'a str,
+    pub link: &'a str,
+    pub first_seen: &'a str,
+    pub message: &'a str,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, LmkError> {
+        let error = |msg: String| LmkError::Config(format!("template {:?}: {}", source, msg));
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(error(
+                                    "unmatched {, use {{ for a literal {".to_string(),
+                                ))
+                            }
+                        }
+                    }
+                    let field = Field::parse(name.trim()).ok_or_else(|| {
+                        let names: Vec<_> = Field::NAMES.iter().map(|(x, _)| *x).collect();
+                        error(format!(
+                            "unknown field {{{}}}, known fields are {}",
+                            name,
+                            names.join(", ")
+                        ))
+                    })?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err(error("unmatched }, use }} for a literal }".to_string())),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    // Returns the names of the fields in the template, e.g., "match".
+    pub fn field_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.parts.iter().filter_map(|x| match x {
+            Part::Field(field) => Field::NAMES
+                .iter()
+                .find(|(_, x)| x == field)
+                .map(|(name, _)| *name),
+            Part::Text(_) => None,
+        })
+    }
+
+    pub fn render(&self, fields: &Fields) -> String {
+        self.parts
+            .iter()
+            .map(|x| match x {
+                Part::Text(x) => x.as_str(),
+                Part::Field(Field::Description) => fields.description,
+                Part::Field(Field::Uri) => fields.uri,
+                Part::Field(Field::Text) => fields.text,
+                Part::Field(Field::Match) => fields.m,
+                Part::Field(Field::Link) => fields.link,
+                Part::Field(Field::FirstSeen) => fields.first_seen,
+                Part::Field(Field::Message) => fields.message,
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = LmkError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Template::parse(&source)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() -> Result<(), LmkError> {
+        let fields = Fields {
+            description: "Whitney curators",
+            uri: "https://whitney.org/jobs",
+            text: "Curator",
+            m: "Assistant Curator",
+            link: "https://whitney.org/jobs/1",
+            first_seen: "2022-10-21T07:28:00Z",
+            message: "Found match: Assistant Curator",
+        };
+        assert_eq!(
+            Template::parse("{description}: {match} (first seen {first_seen})\n{link}")?
+                .render(&fields),
+            "Whitney curators: Assistant Curator (first seen 2022-10-21T07:28:00Z)\nhttps://whitney.org/jobs/1"
+        );
+        assert_eq!(
+            Template::parse("{{{ uri }}} {message}")?.render(&fields),
+            "{https://whitney.org/jobs} Found match: Assistant Curator"
+        );
+        assert_eq!(Template::parse("")?.render(&fields), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            Template::parse("{title}"),
+            Err(LmkError::Config(
+                "template \"{title}\": unknown field {title}, known fields are description, uri, text, match, link, first_seen, message".to_string()
+            ))
+        );
+        assert!(Template::parse("{match").is_err());
+        assert!(Template::parse("match}").is_err());
+        assert!(serde_yaml::from_str::<Template>("\"{matches}\"").is_err());
+        assert_eq!(
+            String::from(serde_yaml::from_str::<Template>("\"{match}\"")?),
+            "{match}"
+        );
+        Ok(())
+    }
+}
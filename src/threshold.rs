@@ -0,0 +1,100 @@
+// Numeric watches for threshold mode targets, e.g., the number of open postings or a ticket price.
+
+use regex::Regex;
+
+use crate::myscraper::Target;
+
+// Extracts a number from text. If pattern is given the number is taken from its first capture
+// group (or the whole match if it has no groups), otherwise the first number in text is used.
+// Thousands separators are ignored, e.g., "$1,250.50" is 1250.5.
+pub fn extract_number(text: &str, pattern: Option<&Regex>) -> Option<f64> {
+    let text = match pattern {
+        Some(pattern) => {
+            let captures = pattern.captures(text)?;
+            captures.get(1).or_else(|| captures.get(0))?.as_str()
+        }
+        None => text,
+    };
+    let number = Regex::new(r"-?\d[\d,]*(\.\d+)?").unwrap();
+    number.find(text)?.as_str().replace(',', "").parse().ok()
+}
+
+// Returns the notifications for a threshold target whose value changed from previous to current.
+// previous is None for the first observation, in which case only values that are already past a
+// threshold are reported.
+pub fn alerts(target: &Target, previous: Option<f64>, current: f64) -> Vec<String> {
+    let mut alerts = vec![];
+    let was = previous.map_or(String::new(), |p| format!(" (was {})", p));
+    if let Some(above) = target.above {
+        if current > above && previous.is_none_or(|p| p <= above) {
+            alerts.push(format!("Value {} rose above {}{}", current, above, was));
+        }
+    }
+    if let Some(below) = target.below {
+        if current < below && previous.is_none_or(|p| p >= below) {
+            alerts.push(format!("Value {} dropped below {}{}", current, below, was));
+        }
+    }
+    if let (Some(percent), Some(previous)) = (target.change_percent, previous) {
+        if previous != 0.0 {
+            let change = (current - previous) / previous.abs() * 100.0;
+            if change.abs() > percent {
+                alerts.push(format!(
+                    "Value changed by {:+.1}% from {} to {}",
+                    change, previous, current
+                ));
+            }
+        }
+    }
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_number() {
+        assert_eq!(extract_number("12 open positions", None), Some(12.0));
+        assert_eq!(extract_number("Tickets from $1,250.50", None), Some(1250.5));
+        assert_eq!(extract_number("no numbers here", None), None);
+
+        let pattern = Regex::new(r"Adults:\s*\$(\S+)").unwrap();
+        assert_eq!(
+            extract_number("Seniors: $18 Adults: $25", Some(&pattern)),
+            Some(25.0)
+        );
+        assert_eq!(extract_number("Seniors: $18", Some(&pattern)), None);
+
+        let pattern = Regex::new(r"\d+ jobs").unwrap();
+        assert_eq!(extract_number("2022: 7 jobs", Some(&pattern)), Some(7.0));
+    }
+
+    #[test]
+    fn test_alerts() {
+        let target = Target {
+            above: Some(10.0),
+            below: Some(5.0),
+            change_percent: Some(50.0),
+            ..Default::default()
+        };
+        // First observations only alert when past a threshold.
+        assert!(alerts(&target, None, 7.0).is_empty());
+        assert_eq!(alerts(&target, None, 11.0), vec!["Value 11 rose above 10"]);
+
+        assert!(alerts(&target, Some(7.0), 8.0).is_empty());
+        assert_eq!(
+            alerts(&target, Some(8.0), 12.0),
+            vec!["Value 12 rose above 10 (was 8)"]
+        );
+        // Staying above the threshold isn't a crossing.
+        assert!(alerts(&target, Some(12.0), 13.0).is_empty());
+        assert_eq!(
+            alerts(&target, Some(9.0), 4.0),
+            vec![
+                "Value 4 dropped below 5 (was 9)",
+                "Value changed by -55.6% from 9 to 4"
+            ]
+        );
+    }
+}
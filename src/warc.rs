@@ -0,0 +1,255 @@
+// Archival of fetched pages in WARC files (https://iipc.github.io/warc-specifications/).
+//
+// Every response fetched during a run is written as a request/response record pair, so that we
+// have an auditable copy of what a page looked like when an alert fired. Files are rotated once
+// they grow past a maximum size.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{SecondsFormat, Utc};
+
+use crate::fetcher::Response;
+
+// Headers describing the transfer of the body, they don't hold for the archived (decoded) body.
+const TRANSFER_HEADERS: [&str; 3] = ["content-encoding", "content-length", "transfer-encoding"];
+
+// The file currently written to.
+struct WarcFile {
+    file: File,
+    size: u64,
+}
+
+pub struct WarcWriter {
+    // Directory the WARC files are written to.
+    dir: PathBuf,
+    // Files are rotated once they're larger than max_size bytes.
+    max_size: u64,
+    // Prefix of the file names, files are named <prefix>-<sequence number>.warc. Sequence numbers
+    // of files that already exist (e.g., of a run started in the same second) are skipped.
+    prefix: String,
+    state: Mutex<(Option<WarcFile>, u32)>,
+}
+
+impl WarcWriter {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        let prefix = format!("lmk-{}", Utc::now().format("%Y%m%d%H%M%S"));
+        WarcWriter {
+            dir,
+            max_size,
+            prefix,
+            state: Mutex::new((None, 0)),
+        }
+    }
+
+    // Writes a request and a response record for the response fetched for uri. Returns the id of
+    // the response record.
+    pub fn write(&self, uri: &str, response: &Response) -> io::Result<String> {
+        let date = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let request_id = record_id();
+        let response_id = record_id();
+
+        let host = url::Url::parse(uri)
+            .ok()
+            .and_then(|x| x.host_str().map(|x| x.to_string()))
+            .unwrap_or_default();
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", uri, host);
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            response.status,
+            reqwest::StatusCode::from_u16(response.status)
+                .ok()
+                .and_then(|x| x.canonical_reason())
+                .unwrap_or_default()
+        );
+        for (name, value) in &response.headers {
+            if !TRANSFER_HEADERS.contains(&name.to_lowercase().as_str()) {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        head.push_str("\r\n");
+        // Binary responses (PDFs) come in data instead of body.
+        let http_response = [head.as_bytes(), response.body.as_bytes(), &response.data].concat();
+
+        let mut records = vec![];
+        write_record(
+            &mut records,
+            &[
+                ("WARC-Type", "request"),
+                ("WARC-Record-ID", &request_id),
+                ("WARC-Date", &date),
+                ("WARC-Target-URI", uri),
+                ("WARC-Concurrent-To", &response_id),
+                ("Content-Type", "application/http;msgtype=request"),
+            ],
+            request.as_bytes(),
+        )?;
+        write_record(
+            &mut records,
+            &[
+                ("WARC-Type", "response"),
+                ("WARC-Record-ID", &response_id),
+                ("WARC-Date", &date),
+                ("WARC-Target-URI", uri),
+                ("Content-Type", "application/http;msgtype=response"),
+            ],
+            &http_response,
+        )?;
+        self.append(&records)?;
+        Ok(response_id)
+    }
+
+    // Appends records to the current file, starting a new file if it's too large.
+    fn append(&self, records: &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let (current, sequence) = &mut *state;
+        if current.as_ref().is_none_or(|x| x.size >= self.max_size) {
+            fs::create_dir_all(&self.dir)?;
+            let mut file = loop {
+                let path = self
+                    .dir
+                    .join(format!("{}-{:05}.warc", self.prefix, sequence));
+                *sequence += 1;
+                match OpenOptions::new().write(true).create_new(true).open(&path) {
+                    Ok(file) => {
+                        log::info!("writing warc records to {:?}", path);
+                        break file;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(e),
+                }
+            };
+            let mut info = vec![];
+            write_record(
+                &mut info,
+                &[
+                    ("WARC-Type", "warcinfo"),
+                    ("WARC-Record-ID", &record_id()),
+                    (
+                        "WARC-Date",
+                        &Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                    ),
+                    ("Content-Type", "application/warc-fields"),
+                ],
+                format!("software: lmk/{}\r\n", env!("CARGO_PKG_VERSION")).as_bytes(),
+            )?;
+            file.write_all(&info)?;
+            *current = Some(WarcFile {
+                file,
+                size: info.len() as u64,
+            });
+        }
+        let current = current.as_mut().unwrap();
+        current.file.write_all(records)?;
+        current.size += records.len() as u64;
+        Ok(())
+    }
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", uuid::Uuid::new_v4())
+}
+
+fn write_record(out: &mut Vec<u8>, headers: &[(&str, &str)], block: &[u8]) -> io::Result<()> {
+    write!(out, "WARC/1.1\r\n")?;
+    for (name, value) in headers {
+        write!(out, "{}: {}\r\n", name, value)?;
+    }
+    write!(out, "Content-Length: {}\r\n\r\n", block.len())?;
+    out.write_all(block)?;
+    write!(out, "\r\n\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &str) -> Response {
+        Response {
+            url: "https://museum.org/careers".to_string(),
+            status: 200,
+            headers: vec![
+                ("content-type".to_string(), "text/html".to_string()),
+                ("content-encoding".to_string(), "gzip".to_string()),
+            ],
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_write() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("lmk-test-warc-write-{}", std::process::id()));
+        let writer = WarcWriter::new(dir.clone(), 1 << 20);
+        let id = writer.write("https://museum.org/careers", &response("<li>Curator</li>"))?;
+        assert!(id.starts_with("<urn:uuid:"));
+
+        let files: Vec<_> = fs::read_dir(&dir)?.collect::<Result<_, _>>()?;
+        assert_eq!(files.len(), 1);
+        let contents = fs::read_to_string(files[0].path())?;
+        assert!(contents.starts_with("WARC/1.1\r\nWARC-Type: warcinfo\r\n"));
+        assert!(contents.contains("WARC-Type: request\r\nWARC-Record-ID: <urn:uuid:"));
+        assert!(contents.contains(&format!(
+            "WARC-Type: response\r\nWARC-Record-ID: {}\r\n",
+            id
+        )));
+        assert!(contents.contains(&format!("WARC-Concurrent-To: {}\r\n", id)));
+        let block = "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\r\n<li>Curator</li>";
+        assert!(contents.contains(&format!(
+            "Content-Length: {}\r\n\r\n{}\r\n\r\n",
+            block.len(),
+            block
+        )));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("lmk-test-warc-rotation-{}", std::process::id()));
+        let writer = WarcWriter::new(dir.clone(), 1000);
+        for _ in 0..3 {
+            writer.write("https://museum.org/careers", &response(&"a".repeat(600)))?;
+        }
+        let mut files: Vec<_> = fs::read_dir(&dir)?
+            .map(|x| x.map(|x| x.file_name().into_string().unwrap()))
+            .collect::<Result<_, _>>()?;
+        files.sort();
+        assert_eq!(files.len(), 3);
+        assert!(files[0].ends_with("-00000.warc"));
+        assert!(files[2].ends_with("-00002.warc"));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_existing_files() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("lmk-test-warc-existing-{}", std::process::id()));
+        // Writers of runs started in the same second share a prefix, neither overwrites the other.
+        let first = WarcWriter::new(dir.clone(), 1 << 20);
+        let second = WarcWriter {
+            prefix: first.prefix.clone(),
+            ..WarcWriter::new(dir.clone(), 1 << 20)
+        };
+        let ids = [
+            first.write("https://museum.org/careers", &response("<li>Curator</li>"))?,
+            second.write(
+                "https://museum.org/careers",
+                &response("<li>Registrar</li>"),
+            )?,
+        ];
+        let contents: Vec<_> = fs::read_dir(&dir)?
+            .map(|x| fs::read_to_string(x?.path()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(contents.len(), 2);
+        for id in ids {
+            assert!(contents.iter().any(|x| x.contains(&id)));
+        }
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
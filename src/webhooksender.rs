@@ -0,0 +1,250 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::error::LmkError;
+use crate::myscraper::{Sender, Target};
+use crate::notify::{self, send_with_retries};
+use crate::template::{Fields, Template};
+
+// POSTs a JSON payload rendered from a template to a webhook, addr is the webhook url. Failures
+// are retried, see notify::send_with_retries.
+//
+// The template is any JSON value whose strings are message templates (see template::Template)
+// without {match} and {first_seen}, which senders don't get, e.g., {"text": "{uri}: {message}"}
+// for Slack or Mattermost, {"content": "{uri}: {message}"} for Discord.
+pub struct WebhookSender {
+    client: reqwest::blocking::Client,
+    template: Payload,
+    // Delay before the first retry, doubled for every other retry.
+    retry_delay: Duration,
+}
+
+impl Sender for WebhookSender {
+    fn name(&self) -> String {
+        "webhook".to_string()
+    }
+
+    fn send(&self, addr: &str, target: &Target, msg: String, link: &str) -> Result<(), LmkError> {
+        let payload = self.template.render(&Fields {
+            description: &target.description,
+            uri: &target.uri,
+            text: &target.text,
+            link,
+            message: &msg,
+            ..Default::default()
+        });
+        let request = self.client.post(addr).json(&payload);
+        send_with_retries(request, "webhook", self.retry_delay)
+    }
+}
+
+impl WebhookSender {
+    const DEFAULT_TEMPLATE: &str = r#"{"text": "{uri}: {message}"}"#;
+
+    // Creates a new Sender with the template in template_path, or the default template if it's
+    // None.
+    pub fn new(template_path: Option<&Path>) -> Result<Self, LmkError> {
+        let template = match template_path {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| LmkError::Config(format!("{}: {}", path.display(), e)))?,
+            None => Self::DEFAULT_TEMPLATE.to_string(),
+        };
+        Self::with_template(&template, notify::DEFAULT_RETRY_DELAY)
+    }
+
+    fn with_template(template: &str, retry_delay: Duration) -> Result<Self, LmkError> {
+        let template: Value = serde_json::from_str(template)
+            .map_err(|e| LmkError::Config(format!("invalid webhook template: {}", e)))?;
+        let template = Payload::parse(template)?;
+        let client = reqwest::blocking::Client::builder()
+            .timeout(notify::TIMEOUT)
+            .build()
+            .map_err(|e| LmkError::Config(format!("webhook: {}", e)))?;
+        Ok(WebhookSender {
+            client,
+            template,
+            retry_delay,
+        })
+    }
+}
+
+// A JSON payload whose strings are templates.
+enum Payload {
+    String(Template),
+    Array(Vec<Payload>),
+    Object(Vec<(String, Payload)>),
+    Other(Value),
+}
+
+impl Payload {
+    fn parse(value: Value) -> Result<Payload, LmkError> {
+        Ok(match value {
+            Value::String(s) => {
+                let template = Template::parse(&s)?;
+                // The match and when it was first seen are only known to message templates.
+                if let Some(name) = template
+                    .field_names()
+                    .find(|x| ["match", "first_seen"].contains(x))
+                {
+                    return Err(LmkError::Config(format!(
+                        "webhook template {:?}: {{{}}} is only known to message templates, use {{message}}",
+                        s, name
+                    )));
+                }
+                Payload::String(template)
+            }
+            Value::Array(xs) => Payload::Array(
+                xs.into_iter()
+                    .map(Payload::parse)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(xs) => Payload::Object(
+                xs.into_iter()
+                    .map(|(k, v)| Ok((k, Payload::parse(v)?)))
+                    .collect::<Result<_, LmkError>>()?,
+            ),
+            x => Payload::Other(x),
+        })
+    }
+
+    fn render(&self, fields: &Fields) -> Value {
+        match self {
+            Payload::String(x) => Value::String(x.render(fields)),
+            Payload::Array(xs) => Value::Array(xs.iter().map(|x| x.render(fields)).collect()),
+            Payload::Object(xs) => Value::Object(
+                xs.iter()
+                    .map(|(k, v)| (k.clone(), v.render(fields)))
+                    .collect(),
+            ),
+            Payload::Other(x) => x.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::matchers::{eq, json_decoded, request};
+    use httptest::responders::status_code;
+    use httptest::{all_of, cycle, Expectation};
+    use serde_json::json;
+
+    fn target() -> Target {
+        Target {
+            uri: "https://museum.org/jobs".to_string(),
+            text: "Curator".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render() -> Result<(), Box<dyn std::error::Error>> {
+        let template = Payload::parse(json!({
+            "embeds": [{"title": "{text} at {uri}", "description": "{{{message}}}", "url": "{link}"}],
+        }))?;
+        let fields = Fields {
+            uri: "https://museum.org/jobs",
+            text: "Curator",
+            link: "https://museum.org/jobs/1",
+            message: "Found match: \"Curator\"",
+            ..Default::default()
+        };
+        assert_eq!(
+            template.render(&fields),
+            json!({
+                "embeds": [{
+                    "title": "Curator at https://museum.org/jobs",
+                    "description": "{Found match: \"Curator\"}",
+                    "url": "https://museum.org/jobs/1",
+                }],
+            })
+        );
+        assert!(Payload::parse(json!({"text": "{title}"})).is_err());
+        assert!(matches!(
+            Payload::parse(json!({"text": ["{match} since {first_seen}"]})),
+            Err(LmkError::Config(e)) if e.contains("{match} is only known to message templates")
+        ));
+        assert!(WebhookSender::with_template("{\"text\": ", Duration::ZERO).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_webhook_sender() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = httptest::Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/hook"),
+                request::body(json_decoded(eq(json!({
+                    "text": "https://museum.org/jobs: Found match: Curator"
+                })))),
+            ])
+            .times(3)
+            .respond_with(cycle![
+                status_code(500),
+                status_code(503),
+                status_code(200),
+            ]),
+        );
+        let sender = WebhookSender::with_template(WebhookSender::DEFAULT_TEMPLATE, Duration::ZERO)?;
+        let hook = server.url_str("/hook");
+        sender.send(
+            &hook,
+            &target(),
+            "Found match: Curator".to_string(),
+            &target().uri,
+        )?;
+        server.verify_and_clear();
+
+        // Gives up after MAX_ATTEMPTS.
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/hook"))
+                .times(3)
+                .respond_with(status_code(500)),
+        );
+        assert_eq!(
+            sender.send(&hook, &target(), "x".to_string(), &target().uri),
+            Err(LmkError::Notify(
+                "webhook: 500 Internal Server Error".to_string()
+            ))
+        );
+        server.verify_and_clear();
+
+        // Rate limited posts are retried after the Retry-After.
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/hook"))
+                .times(2)
+                .respond_with(cycle![
+                    status_code(429).insert_header("Retry-After", "0.01"),
+                    status_code(200),
+                ]),
+        );
+        sender.send(&hook, &target(), "x".to_string(), &target().uri)?;
+        server.verify_and_clear();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/hook"))
+                .times(1)
+                .respond_with(status_code(429).insert_header("Retry-After", "3600")),
+        );
+        assert_eq!(
+            sender.send(&hook, &target(), "x".to_string(), &target().uri),
+            Err(LmkError::Notify(
+                "webhook: rate limited for 3600s".to_string()
+            ))
+        );
+        server.verify_and_clear();
+
+        // Client errors aren't retried.
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/hook"))
+                .times(1)
+                .respond_with(status_code(404)),
+        );
+        assert_eq!(
+            sender.send(&hook, &target(), "x".to_string(), &target().uri),
+            Err(LmkError::Notify("webhook: 404 Not Found".to_string()))
+        );
+        Ok(())
+    }
+}